@@ -1,22 +1,41 @@
 mod common;
+mod ignore;
 mod init;
+mod merge;
 mod metafiles;
+mod objects;
+mod patch;
 mod preview;
 mod snapshot;
+mod status;
 
-use crate::commands::common::{get_kifi, get_user};
-use crate::commands::init::update_file_cache;
-use crate::commands::metafiles::Paths;
-use crate::commands::preview::{generate_diffs, read_lines};
-use crate::commands::snapshot::{gen_name, snap_file};
+use crate::commands::common::{
+    advance_head, check_tag_name, find_snapshot, format_time, get_branches, get_config, get_head,
+    get_head_snapshot, get_kifi, get_merge_state, get_tags, get_user, get_user_setting,
+    resolve_conflicts, resolve_name, set_branches, set_config, set_head, set_merge_state, set_tags,
+    set_user,
+};
+use crate::commands::init::{files_in, get_kignore, track_files, update_file_cache};
+use crate::commands::merge::{has_conflict_markers, merge_file, write_lines, FileMerge};
+use crate::commands::metafiles::{MergeState, Paths};
+use crate::commands::objects::{
+    checkout_tree, hash_object, read_tree, remove_file, restore_file, restore_tree, write_tree,
+};
+use crate::commands::patch::{apply_file, parse_patch, patch_file_name, patch_header};
+use crate::commands::preview::{diff_sources, to_lines, Source};
+pub use crate::commands::preview::{DiffOptions, Summary, Whitespace, WordDiff, DIFF_ALGORITHMS};
+use crate::commands::snapshot::{gen_name, get_message, snap_file, stash};
+use crate::commands::status::Status;
 use crate::errors::Error;
-use crate::output::Output;
-use dirs::config_local_dir;
-use fs_extra::dir;
-use metafiles::{FileCache, FileStatus, Metadata, Snapshots, User};
+use crate::output::{DebugOutput, Output};
+use metafiles::{
+    Branches, Config, FileCache, FileStatus, Head, Metadata, Snapshot, Snapshots, Tag, Tags, Tree,
+    User,
+};
 use serde_cbor::{from_reader, to_writer};
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
 /// Initialises a kifi repo
 pub fn initialise(output: &mut dyn Output, provided_path: Option<PathBuf>) -> Result<(), Error> {
@@ -27,16 +46,22 @@ pub fn initialise(output: &mut dyn Output, provided_path: Option<PathBuf>) -> Re
             output.add_str("Reinitialising kifi");
             path
         }
-        Err(Error::KifiNotInitialised) => Paths::from_path_buf(PathBuf::from("."))?,
+        Err(Error::KifiNotInitialised) => {
+            Paths::from_path_buf(provided_path.clone().unwrap_or_else(|| PathBuf::from(".")))?
+        }
         Err(e) => return Err(e),
     };
 
     fs::create_dir(path.kifi()).map_err(Error::CreateDirectory)?;
+    fs::create_dir(path.objects()).map_err(Error::CreateDirectory)?;
     let metadata_file = fs::File::create(path.meta()).map_err(Error::CreateFile)?;
     fs::File::create(path.tracked()).map_err(Error::CreateFile)?;
 
     let snapshots_file = fs::File::create(path.snaps()).map_err(Error::CreateFile)?;
     to_writer(snapshots_file, &Snapshots::new()).map_err(Error::CBORWriter)?;
+    set_head(&path, &Head::new())?;
+    set_branches(&path, &Branches::new())?;
+    set_tags(&path, &Tags::new())?;
 
     let metadata = Metadata::from_pathbuf(path.root())?;
 
@@ -105,9 +130,12 @@ pub fn debug_meta(output: &mut dyn Output, provided_path: Option<PathBuf>) -> Re
     Ok(())
 }
 
-/// Changes status of file to FileStatus::Tracked, see `metafiles`
+/// Changes status of files to FileStatus::Tracked, see `metafiles`
+/// Each name can be a file, a directory or a glob pattern. Ignored files found
+/// through a directory or pattern are skipped unless tracking is forced.
 pub fn track(
-    file_name: &String,
+    file_names: &[String],
+    all: &bool,
     forced: &bool,
     output: &mut dyn Output,
     provided_path: Option<PathBuf>,
@@ -115,19 +143,216 @@ pub fn track(
     let path = get_kifi(&provided_path)?;
     update_file_cache(provided_path)?;
 
+    let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
+    let mut cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
+
+    let pathspecs = if *all {
+        vec![String::from(".")]
+    } else {
+        file_names.to_vec()
+    };
+
+    let mut to_track: Vec<PathBuf> = Vec::new();
+    let mut named: Vec<PathBuf> = Vec::new();
+    let mut skipped = 0;
+    for pathspec in &pathspecs {
+        // Files inside ignored directories aren't in the cache, but can still be tracked by name.
+        let file_path: PathBuf = Path::new(pathspec)
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .collect();
+        let absent = cache.expand(pathspec).is_empty();
+        let on_disk = path.root().join(&file_path);
+        if absent && on_disk.exists() && !forced {
+            return Err(Error::TrackIgnoredFile(file_path));
+        }
+        // Forcing a directory also reaches the files in ignored directories inside it.
+        if *forced && on_disk.is_dir() {
+            for file in files_in(&path, &file_path)? {
+                cache.add_file(file, FileStatus::Ignored);
+            }
+        } else if absent && on_disk.is_file() {
+            cache.add_file(file_path.to_owned(), FileStatus::Ignored);
+        }
+
+        let files = cache.expand(pathspec);
+        if files.is_empty() {
+            return Err(Error::FileNotFoundInCache(PathBuf::from(pathspec)));
+        }
+
+        let named_exactly = files.len() == 1 && files[0] == &file_path;
+        named.extend(files.iter().map(|&file| file.to_owned()));
+        for file in files {
+            match cache.get_status(file) {
+                Some(FileStatus::Tracked) => {}
+                Some(FileStatus::Ignored) if !forced && !named_exactly => skipped += 1,
+                _ => to_track.push(file.to_owned()),
+            }
+        }
+    }
+    to_track.sort();
+    to_track.dedup();
+
+    for file_path in &to_track {
+        cache.change_status(file_path, FileStatus::Tracked, forced)?;
+        output.add(format!("Tracking {}", file_path.display()));
+    }
+    // Tracking a binary file that conflicted in a merge keeps it as it is now.
+    resolve_conflicts(&path, &named)?;
+
+    output.add(format!("{} file(s) changed state", to_track.len()));
+    if skipped > 0 {
+        output.add(format!(
+            "Skipped {} ignored file(s), use -f to force tracking them",
+            skipped
+        ));
+    }
+
+    let cache_file = fs::File::create(path.filecache()).map_err(Error::CreateFile)?;
+    to_writer(cache_file, &cache).map_err(Error::CBORWriter)?;
+
+    Ok(())
+}
+
+/// Changes status of a tracked file to FileStatus::Untracked, so the next snapshot won't include it
+pub fn untrack(
+    file_name: &String,
+    output: &mut dyn Output,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    update_file_cache(provided_path)?;
+
     let file_path = PathBuf::from(file_name);
 
     let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
     let mut cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
 
-    match cache.change_status(&file_path, FileStatus::Tracked, forced) {
-        Ok(()) => {
-            output.add(format!("Tracking {}", file_path.display()));
-        }
-        Err(e) => {
-            return Err(e);
+    if !cache.has_tracked_file(&file_path) {
+        return Err(Error::FileNotTracked(file_path));
+    }
+    cache.change_status(&file_path, FileStatus::Untracked, &false)?;
+    output.add(format!("Stopped tracking {}", file_path.display()));
+
+    let cache_file = fs::File::create(path.filecache()).map_err(Error::CreateFile)?;
+    to_writer(cache_file, &cache).map_err(Error::CBORWriter)?;
+
+    Ok(())
+}
+
+/// Deletes a tracked file from the working tree and the cache
+pub fn remove(
+    file_name: &String,
+    force: &bool,
+    output: &mut dyn Output,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    update_file_cache(provided_path)?;
+
+    let file_path = PathBuf::from(file_name);
+
+    let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
+    let mut cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
+
+    if !cache.has_tracked_file(&file_path) {
+        return Err(Error::FileNotTracked(file_path));
+    }
+
+    // Without --force, only contents that the last snapshot holds can be deleted.
+    // Binary conflicts of a merge hold the other branch's version if this one deleted them.
+    let destination = path.root().join(&file_path);
+    let conflicted =
+        get_merge_state(&path)?.is_some_and(|state| state.binary_conflicts.contains(&file_path));
+    if !*force && !conflicted && destination.is_file() {
+        let head_tree = match get_head_snapshot(&path)? {
+            Some(name) => {
+                let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
+                let snapshots: Snapshots =
+                    from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+                Some(read_tree(&path, &snapshots.get_head(Some(&name))?.tree)?)
+            }
+            None => None,
+        };
+        let contents = fs::read(&destination).map_err(Error::ReadFile)?;
+        if head_tree.as_ref().and_then(|tree| tree.get(&file_path)) != Some(&hash_object(&contents))
+        {
+            return Err(Error::UncommittedChanges(vec![file_path]));
         }
+    }
+    remove_file(&path, &file_path)?;
+    cache.remove_file(&file_path)?;
+    resolve_conflicts(&path, std::slice::from_ref(&file_path))?;
+    output.add(format!("Removed {}", file_path.display()));
+
+    let cache_file = fs::File::create(path.filecache()).map_err(Error::CreateFile)?;
+    to_writer(cache_file, &cache).map_err(Error::CBORWriter)?;
+
+    Ok(())
+}
+
+/// Moves a file or directory in the working tree, keeping the status of the files in the cache
+pub fn move_file(
+    from: &String,
+    to: &String,
+    output: &mut dyn Output,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    update_file_cache(provided_path)?;
+
+    let from_path = PathBuf::from(from);
+    let mut to_path = PathBuf::from(to);
+
+    let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
+    let mut cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
+
+    // A directory takes every file the cache has inside it along.
+    let moved: Vec<PathBuf> = match path.root().join(&from_path).is_dir() {
+        true => cache
+            .get_keys()
+            .into_iter()
+            .filter(|file| file.starts_with(&from_path))
+            .cloned()
+            .collect(),
+        false => cache
+            .get_status(&from_path)
+            .map(|_| vec![from_path.to_owned()])
+            .unwrap_or_default(),
     };
+    if moved.is_empty() {
+        return Err(Error::FileNotFoundInCache(from_path));
+    }
+
+    // Moving into an existing directory keeps the file's name, like `mv` does.
+    if path.root().join(&to_path).is_dir() {
+        if let Some(file_name) = from_path.file_name() {
+            to_path.push(file_name);
+        }
+    }
+    if path.root().join(&to_path).exists() {
+        return Err(Error::DestinationExists(to_path));
+    }
+
+    let destination = path.root().join(&to_path);
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(Error::CreateDirectory)?;
+    }
+    fs::rename(path.root().join(&from_path), &destination)
+        .map_err(|e| Error::FileCopy(from_path.to_owned(), to_path.to_owned(), e))?;
+
+    for file in moved {
+        let moved_to = match file.strip_prefix(&from_path) {
+            Ok(inside) if !inside.as_os_str().is_empty() => to_path.join(inside),
+            _ => to_path.to_owned(),
+        };
+        cache.rename_file(&file, &moved_to)?;
+    }
+    output.add(format!(
+        "Moved {} to {}",
+        from_path.display(),
+        to_path.display()
+    ));
 
     let cache_file = fs::File::create(path.filecache()).map_err(Error::CreateFile)?;
     to_writer(cache_file, &cache).map_err(Error::CBORWriter)?;
@@ -135,8 +360,13 @@ pub fn track(
     Ok(())
 }
 
-/// Shows diffs
-pub fn preview(output: &mut dyn Output, provided_path: Option<PathBuf>) -> Result<(), Error> {
+/// Shows diffs from the given snapshot or tag, or from the current snapshot
+pub fn preview(
+    output: &mut dyn Output,
+    snapshot: &Option<String>,
+    options: &DiffOptions,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
     let path = get_kifi(&provided_path)?;
     update_file_cache(provided_path)?;
 
@@ -146,25 +376,189 @@ pub fn preview(output: &mut dyn Output, provided_path: Option<PathBuf>) -> Resul
     let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
     let snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
 
-    let last_snapshot = snapshots.get_last()?;
+    let last_snapshot = match snapshot {
+        Some(name) => find_snapshot(&path, &snapshots, name)?,
+        None => snapshots.get_head(get_head_snapshot(&path)?.as_ref())?,
+    };
+    let tree = read_tree(&path, &last_snapshot.tree)?;
+    let algorithm = match options.algorithm {
+        Some(algorithm) => algorithm,
+        None => get_config(&path)?.diff_algorithm,
+    };
 
-    for file in cache.get_keys() {
-        if let FileStatus::Tracked = cache.get_status(file).expect("Keys were fetched from the cache and immediately used, so the corresponding value should exist.") {
-            output.add(file.display().to_string());
-            let file = path.root().join(file);
+    // Each tracked file is compared to the copy stored under the same path in the snapshot.
+    diff_sources(
+        &path,
+        &Source::Snapshot(&tree),
+        &Source::WorkingTree(cache.get_tracked_files()),
+        &[],
+        options,
+        algorithm,
+        output,
+    )
+}
 
-            let current_file = match read_lines(&file) {
-                Ok(v) => v,
-                Err(_) => Vec::new(),
-            };
+/// Shows diffs between two snapshots, or between a snapshot and the working tree
+/// With no snapshots given, the current snapshot is compared to the working tree.
+pub fn diff(
+    output: &mut dyn Output,
+    from: &Option<String>,
+    to: &Option<String>,
+    file_names: &[String],
+    options: &DiffOptions,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    update_file_cache(provided_path)?;
 
-            let snapped_file_path = path.kifi().join(&last_snapshot.name);
-            let snapped_file = match read_lines(&snapped_file_path) {
-                Ok(v) => v,
-                Err(_) => Vec::new(),
-            };
+    let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
+    let cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
 
-            generate_diffs(snapped_file, current_file, output)?;
+    let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
+    let snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+
+    let from_snapshot = match from {
+        Some(name) => find_snapshot(&path, &snapshots, name)?,
+        None => snapshots.get_head(get_head_snapshot(&path)?.as_ref())?,
+    };
+    let from_tree = read_tree(&path, &from_snapshot.tree)?;
+
+    let to_tree = match to {
+        Some(name) => Some(read_tree(
+            &path,
+            &find_snapshot(&path, &snapshots, name)?.tree,
+        )?),
+        None => None,
+    };
+    let to_source = match &to_tree {
+        Some(tree) => Source::Snapshot(tree),
+        None => Source::WorkingTree(cache.get_tracked_files()),
+    };
+
+    let filters: Vec<PathBuf> = file_names.iter().map(PathBuf::from).collect();
+    let algorithm = match options.algorithm {
+        Some(algorithm) => algorithm,
+        None => get_config(&path)?.diff_algorithm,
+    };
+
+    diff_sources(
+        &path,
+        &Source::Snapshot(&from_tree),
+        &to_source,
+        &filters,
+        options,
+        algorithm,
+        output,
+    )
+}
+
+/// Shows how the working tree differs from the current snapshot
+pub fn status(
+    output: &mut dyn Output,
+    porcelain: &bool,
+    show_ignored: &bool,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    update_file_cache(provided_path)?;
+
+    let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
+    let cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
+
+    let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
+    let snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+
+    let tree = match get_head_snapshot(&path)? {
+        Some(name) => Some(read_tree(&path, &snapshots.get_head(Some(&name))?.tree)?),
+        None => None,
+    };
+
+    let status = Status::new(&path, &cache, tree.as_ref())?;
+
+    let renamed: Vec<PathBuf> = status
+        .renamed
+        .iter()
+        .map(|(from, to)| PathBuf::from(format!("{} -> {}", from.display(), to.display())))
+        .collect();
+
+    let mut groups = vec![
+        ("M", "Modified:", &status.modified),
+        ("A", "Added:", &status.added),
+        ("D", "Deleted:", &status.deleted),
+        ("R", "Renamed:", &renamed),
+        ("?", "Untracked:", &status.untracked),
+    ];
+    if *show_ignored {
+        groups.push(("!", "Ignored:", &status.ignored));
+    }
+
+    if *porcelain {
+        for (code, _, files) in groups {
+            for file in files {
+                output.add(format!("{} {}", code, file.display()));
+            }
+        }
+        return Ok(());
+    }
+
+    output.add(format!("On branch {}", get_head(&path)?.branch()));
+    if !status.has_changes() {
+        output.add_str("No changes since the last snapshot.");
+    }
+
+    for (_, heading, files) in groups {
+        if files.is_empty() {
+            continue;
+        }
+        output.add_str("");
+        output.add_str(heading);
+        for file in files {
+            output.add(format!("    {}", file.display()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Shows which of the given paths, relative to the repository's root, are ignored
+/// With `verbose`, the pattern deciding each path is shown along with the file and line it came from,
+/// including negated patterns, and `non_matching` also lists paths that no pattern matches.
+pub fn check_ignore(
+    output: &mut dyn Output,
+    paths: &[String],
+    verbose: &bool,
+    non_matching: &bool,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    let root = path.root();
+    // The global and root ignore files apply to every path, so they are only read once.
+    let root_kignore = get_kignore(root.clone());
+
+    for name in paths {
+        let file: PathBuf = Path::new(name)
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .collect();
+        let is_dir = name.ends_with('/') || root.join(&file).is_dir();
+
+        let mut kignore = root_kignore.clone();
+        kignore.add_parent_files(&root, &file);
+
+        match (kignore.deciding_rule(&file, is_dir), verbose) {
+            (Some(rule), true) => output.add(format!(
+                "{}:{}:{}\t{}",
+                rule.source
+                    .strip_prefix(&root)
+                    .unwrap_or(&rule.source)
+                    .display(),
+                rule.line,
+                rule.text,
+                name
+            )),
+            (Some(rule), false) if !rule.negated => output.add(name.to_owned()),
+            (None, true) if *non_matching => output.add(format!("::\t{}", name)),
+            _ => {}
         }
     }
 
@@ -172,58 +566,111 @@ pub fn preview(output: &mut dyn Output, provided_path: Option<PathBuf>) -> Resul
 }
 
 /// Takes a snapshot
-pub fn snapshot(provided_path: Option<PathBuf>) -> Result<(), Error> {
+pub fn snapshot(
+    message: &Option<String>,
+    message_file: &Option<PathBuf>,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
     let path = get_kifi(&provided_path)?;
     update_file_cache(provided_path)?;
 
+    let merge_state = get_merge_state(&path)?;
+    if let Some(state) = &merge_state {
+        let mut unresolved: Vec<PathBuf> = state
+            .conflicts
+            .iter()
+            .filter(|&file| {
+                has_conflict_markers(&to_lines(
+                    &fs::read(path.root().join(file)).unwrap_or_default(),
+                ))
+            })
+            .cloned()
+            .collect();
+        unresolved.extend(state.binary_conflicts.iter().cloned());
+        unresolved.sort();
+        if !unresolved.is_empty() {
+            return Err(Error::UnresolvedConflicts(unresolved));
+        }
+    }
+
     let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
     let cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
 
     let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
     let mut snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
 
-    let snap_name = gen_name()?;
-    let snap_dir = PathBuf::from(".kifi").join(&snap_name);
-
-    fs::create_dir_all(&snap_dir).map_err(Error::CreateDirectory)?;
-
     let user = get_user()?;
-    snapshots.new_snap(&snap_name, &user);
+    let snap_name = gen_name(&snapshots)?;
 
+    let mut tree = Tree::new();
     for file in cache.get_tracked_files() {
-        snap_file(file, &snap_dir)?;
+        snap_file(file, &path, &mut tree)?;
     }
+    let tree_hash = write_tree(&path, &tree)?;
+
+    // The editor is only opened once the snapshot can be taken, so a written message isn't lost.
+    let message = match (&merge_state, message, message_file) {
+        (Some(state), None, None) => state.message.to_owned(),
+        _ => get_message(message, message_file, &path)?,
+    };
+
+    let mut parents: Vec<String> = get_head_snapshot(&path)?.into_iter().collect();
+    if let Some(state) = merge_state {
+        parents.push(state.theirs);
+    }
+
+    snapshots.new_snap(&snap_name, &user, tree_hash, message, parents);
 
     let snapshots_file = fs::File::create(path.snaps()).map_err(Error::CreateFile)?;
     to_writer(snapshots_file, &snapshots).map_err(Error::CBORWriter)?;
 
+    advance_head(&path, &snap_name)?;
+    set_merge_state(&path, None)?;
+
     Ok(())
 }
 
-/// Shows previous commits stored in Snapshots
-pub fn log(output: &mut dyn Output, provided_path: Option<PathBuf>) -> Result<(), Error> {
+/// Shows previous commits stored in Snapshots, starting from the given snapshot or tag, or from HEAD
+pub fn log(
+    output: &mut dyn Output,
+    snapshot: &Option<String>,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
     let path = get_kifi(&provided_path)?;
 
     let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
     let snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+    let tags = get_tags(&path)?;
 
-    for snap in snapshots.into_iter() {
-        output.add(format!("snapshot {}", snap.name));
+    let start = match snapshot {
+        Some(name) => Some(find_snapshot(&path, &snapshots, name)?.name.to_owned()),
+        None => get_head_snapshot(&path)?,
+    };
+    let ancestry = match &start {
+        Some(name) => snapshots.ancestry(name),
+        None => Vec::new(),
+    };
+
+    for snap in ancestry {
+        let snap_tags = tags.names_for(&snap.name);
+        if snap_tags.is_empty() {
+            output.add(format!("snapshot {}", snap.name));
+        } else {
+            let snap_tags: Vec<String> = snap_tags.iter().map(|t| format!("tag: {}", t)).collect();
+            output.add(format!("snapshot {} ({})", snap.name, snap_tags.join(", ")));
+        }
+        if snap.parents.len() > 1 {
+            output.add(format!("Merge: {}", snap.parents.join(" ")));
+        }
         output.add(format!(
             "Created by:\n{}\n{}",
             snap.author, snap.author_email
         ));
-        output.add(format!(
-            "Created on {} UTC",
-            time::OffsetDateTime::from(snap.created)
-                .format(
-                    &time::format_description::parse(
-                        "[year]-[month]-[day] [hour]:[minute]:[second]"
-                    )
-                    .map_err(|_| Error::InvalidTime(snap.created))?
-                )
-                .map_err(|_| Error::InvalidTime(snap.created))?
-        ));
+        output.add(format!("Created on {} UTC", format_time(snap.created)?));
+        output.add_str("");
+        for line in snap.message.lines() {
+            output.add(format!("    {}", line));
+        }
         output.add_str("");
     }
 
@@ -231,32 +678,66 @@ pub fn log(output: &mut dyn Output, provided_path: Option<PathBuf>) -> Result<()
 }
 
 /// Restore snapshot
+/// Tracked files with changes since the last snapshot are not overwritten unless `force` is set,
+/// in which case their contents are stashed first.
 pub fn revert(
     output: &mut dyn Output,
     name: String,
+    force: &bool,
     provided_path: Option<PathBuf>,
 ) -> Result<(), Error> {
     let path = get_kifi(&provided_path)?;
+    update_file_cache(provided_path)?;
 
     let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
-    let snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+    let mut snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+
+    let name = resolve_name(&path, &snapshots, &name)?;
 
     match snapshots.find(name) {
         metafiles::SearchResults::FoundExact(snapshot) => {
-            let snap_dir = path.kifi().join(snapshot.name);
-
-            for file in fs::read_dir(snap_dir).map_err(Error::GetCurrentDirectory)? {
-                let file = file.map_err(Error::ReadFile)?;
-                if file.metadata().map_err(Error::ReadFile)?.is_dir() {
-                    dir::copy(file.path(), ".", &dir::CopyOptions::new().overwrite(true)).map_err(
-                        |e| Error::DirCopy(file.path(), PathBuf::from(file.file_name()), e),
-                    )?;
-                } else {
-                    fs::copy(file.path(), "file.file_name()").map_err(|e| {
-                        Error::FileCopy(file.path(), PathBuf::from(file.file_name()), e)
-                    })?;
+            let tree = read_tree(&path, &snapshot.tree)?;
+
+            let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
+            let cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
+
+            let head = get_head_snapshot(&path)?;
+            let head_tree = match &head {
+                Some(name) => Some(read_tree(&path, &snapshots.get_head(Some(name))?.tree)?),
+                None => None,
+            };
+
+            // Changed files are only at risk if the snapshot has different contents for them.
+            let status = Status::new(&path, &cache, head_tree.as_ref())?;
+            let untracked = status.untracked_overwritten_by(&path, &tree, head_tree.as_ref())?;
+            if !untracked.is_empty() {
+                return Err(Error::UntrackedOverwritten(untracked));
+            }
+            let dirty = status.overwritten_by(&path, &tree, None)?;
+
+            if !dirty.is_empty() {
+                if !*force {
+                    return Err(Error::UncommittedChanges(dirty));
                 }
+
+                let stash_name = stash(
+                    &path,
+                    &cache,
+                    &mut snapshots,
+                    head,
+                    format!("Changes stashed before reverting to {}", snapshot.name),
+                )?;
+                let snapshots_file = fs::File::create(path.snaps()).map_err(Error::CreateFile)?;
+                to_writer(snapshots_file, &snapshots).map_err(Error::CBORWriter)?;
+
+                output.add(format!("Stashed uncommitted changes as {}", stash_name));
+                output.add(format!(
+                    "Use `kifi restore {} -- <paths>` to bring them back.",
+                    stash_name
+                ));
             }
+
+            restore_tree(&path, &tree)?;
         }
         metafiles::SearchResults::FoundSimilar(matching_snapshots) => {
             output.add_str("Snapshot not found. Did you mean one of these?");
@@ -266,14 +747,7 @@ pub fn revert(
                     snap.name,
                     snap.author,
                     snap.author_email,
-                    time::OffsetDateTime::from(snap.created)
-                        .format(
-                            &time::format_description::parse(
-                                "[year]-[month]-[day] [hour]:[minute]:[second]"
-                            )
-                            .map_err(|_| Error::InvalidTime(snap.created))?
-                        )
-                        .map_err(|_| Error::InvalidTime(snap.created))?
+                    format_time(snap.created)?
                 ));
             }
         }
@@ -283,16 +757,642 @@ pub fn revert(
     Ok(())
 }
 
-/// Register a user, to reflect them as the author in later commits
-pub fn register(name: &String, email: &String) -> Result<(), Error> {
-    let user = User::new(name, email)?;
+/// Restores the given files or directories from a snapshot, leaving other files untouched
+pub fn restore(
+    output: &mut dyn Output,
+    name: &str,
+    file_names: &[String],
+    dry_run: &bool,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+
+    let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
+    let snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+
+    let snapshot = find_snapshot(&path, &snapshots, name)?;
+    let tree = read_tree(&path, &snapshot.tree)?;
+
+    let mut files: Vec<(&PathBuf, &String)> = Vec::new();
+    for file_name in file_names {
+        let matching = tree.files_under(Path::new(file_name));
+        if matching.is_empty() {
+            return Err(Error::FileNotInSnapshot(
+                PathBuf::from(file_name),
+                snapshot.name.to_owned(),
+            ));
+        }
+        files.extend(matching);
+    }
+    files.sort();
+    files.dedup();
+
+    for (file, hash) in &files {
+        let destination = path.root().join(file);
+        let action = match fs::read(&destination) {
+            Ok(contents) if &hash_object(&contents) == *hash => "Unchanged",
+            Ok(_) => "Overwriting",
+            Err(_) => "Creating",
+        };
+
+        if *dry_run {
+            output.add(format!("Would restore {} ({})", file.display(), action));
+        } else {
+            restore_file(&path, hash, &destination)?;
+            output.add(format!("Restored {} ({})", file.display(), action));
+        }
+    }
+
+    if !*dry_run {
+        track_files(provided_path, files.iter().map(|(file, _)| *file).collect())?;
+    }
+
+    Ok(())
+}
+
+/// Lists branches, or creates or deletes one
+pub fn branch(
+    output: &mut dyn Output,
+    name: &Option<String>,
+    delete: &bool,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    let head = get_head(&path)?;
+    let mut branches = get_branches(&path)?;
+
+    match name {
+        Some(name) if *delete => {
+            if name == head.branch() {
+                return Err(Error::DeleteCurrentBranch(name.to_owned()));
+            }
+            let snapshot = branches.delete(name)?;
+            output.add(format!("Deleted branch {} (was {})", name, snapshot));
+        }
+        Some(name) => {
+            let snapshot = branches
+                .get(head.branch())
+                .cloned()
+                .ok_or(Error::BranchWithoutSnapshots)?;
+            branches.create(name, &snapshot)?;
+            output.add(format!("Created branch {} at {}", name, snapshot));
+        }
+        None => {
+            if !branches.contains(head.branch()) {
+                output.add(format!("* {} (no snapshots)", head.branch()));
+            }
+            for (branch, snapshot) in branches.iter() {
+                let marker = if branch == head.branch() { "*" } else { " " };
+                output.add(format!("{} {}\t{}", marker, branch, snapshot));
+            }
+            return Ok(());
+        }
+    }
+
+    set_branches(&path, &branches)
+}
+
+/// Switches to another branch, restoring the snapshot it points to
+pub fn switch(
+    output: &mut dyn Output,
+    branch: &String,
+    create: &bool,
+    force: &bool,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    update_file_cache(provided_path.clone())?;
+    let mut head = get_head(&path)?;
+    let mut branches = get_branches(&path)?;
+
+    if *create {
+        let snapshot = branches
+            .get(head.branch())
+            .cloned()
+            .ok_or(Error::BranchWithoutSnapshots)?;
+        branches.create(branch, &snapshot)?;
+        set_branches(&path, &branches)?;
+    }
+
+    let target = branches
+        .get(branch)
+        .ok_or_else(|| Error::BranchNotFound(branch.to_owned()))?;
+
+    let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
+    let mut snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+
+    let target_tree = read_tree(&path, &snapshots.get_head(Some(target))?.tree)?;
+    let current = branches.get(head.branch()).cloned();
+    let current_tree = match &current {
+        Some(current) => Some(read_tree(&path, &snapshots.get_head(Some(current))?.tree)?),
+        None => None,
+    };
+
+    let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
+    let cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
+
+    let status = Status::new(&path, &cache, current_tree.as_ref())?;
+    let untracked = status.untracked_overwritten_by(&path, &target_tree, current_tree.as_ref())?;
+    if !untracked.is_empty() {
+        return Err(Error::UntrackedOverwritten(untracked));
+    }
+    let dirty = status.overwritten_by(&path, &target_tree, current_tree.as_ref())?;
+    if !dirty.is_empty() {
+        if !*force {
+            return Err(Error::UncommittedChanges(dirty));
+        }
+
+        let stash_name = stash(
+            &path,
+            &cache,
+            &mut snapshots,
+            current,
+            format!("Changes stashed before switching to {}", branch),
+        )?;
+        let snapshots_file = fs::File::create(path.snaps()).map_err(Error::CreateFile)?;
+        to_writer(snapshots_file, &snapshots).map_err(Error::CBORWriter)?;
+
+        output.add(format!("Stashed uncommitted changes as {}", stash_name));
+        output.add(format!(
+            "Use `kifi restore {} -- <paths>` to bring them back.",
+            stash_name
+        ));
+    }
+
+    checkout_tree(&path, current_tree.as_ref(), &target_tree)?;
+
+    // Files in the restored snapshot are tracked on this branch. The cache is updated before HEAD
+    // moves, so a failure leaves the repository on the branch it was on.
+    track_files(
+        provided_path,
+        target_tree.files().map(|(file, _)| file).collect(),
+    )?;
+
+    head.switch(branch);
+    set_head(&path, &head)?;
+
+    output.add(format!("Switched to branch {}", branch));
+
+    Ok(())
+}
+
+/// Merges another branch into the current one
+pub fn merge(
+    output: &mut dyn Output,
+    branch: &String,
+    message: &Option<String>,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    update_file_cache(provided_path.clone())?;
+    let head = get_head(&path)?;
+    let mut branches = get_branches(&path)?;
+
+    if get_merge_state(&path)?.is_some() {
+        return Err(Error::MergeInProgress);
+    }
+
+    let ours = branches
+        .get(head.branch())
+        .cloned()
+        .ok_or(Error::MergeWithoutSnapshots)?;
+    let theirs = branches
+        .get(branch)
+        .cloned()
+        .ok_or_else(|| Error::BranchNotFound(branch.to_owned()))?;
+
+    let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
+    let snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+
+    let ours_tree = read_tree(&path, &snapshots.get_head(Some(&ours))?.tree)?;
+    let theirs_tree = read_tree(&path, &snapshots.get_head(Some(&theirs))?.tree)?;
+
+    // Changes in the working tree are checked before any file is written, so none are lost.
+    let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
+    let cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
+    let status = Status::new(&path, &cache, Some(&ours_tree))?;
+    let untracked = status.untracked_overwritten_by(&path, &theirs_tree, Some(&ours_tree))?;
+
+    let base = snapshots.merge_base(&ours, &theirs);
+    let base_tree = match base {
+        Some(base) if base.name == theirs => {
+            output.add_str("Already up to date.");
+            return Ok(());
+        }
+        Some(base) if base.name == ours => {
+            if !untracked.is_empty() {
+                return Err(Error::UntrackedOverwritten(untracked));
+            }
+            let dirty = status.overwritten_by(&path, &theirs_tree, Some(&ours_tree))?;
+            if !dirty.is_empty() {
+                return Err(Error::UncommittedChanges(dirty));
+            }
+
+            checkout_tree(&path, Some(&ours_tree), &theirs_tree)?;
+            track_files(
+                provided_path,
+                theirs_tree.files().map(|(file, _)| file).collect(),
+            )?;
+            branches.set(head.branch(), &theirs);
+            set_branches(&path, &branches)?;
+            output.add(format!("Fast-forwarded {} to {}", head.branch(), theirs));
+            return Ok(());
+        }
+        Some(base) => read_tree(&path, &base.tree)?,
+        None => Tree::new(),
+    };
+
+    let mut files: Vec<&PathBuf> = base_tree
+        .files()
+        .chain(ours_tree.files())
+        .chain(theirs_tree.files())
+        .map(|(file, _)| file)
+        .collect();
+    files.sort();
+    files.dedup();
+
+    if !untracked.is_empty() {
+        return Err(Error::UntrackedOverwritten(untracked));
+    }
+    // Every file in the snapshots is written, even those only changed on one side.
+    let dirty: Vec<PathBuf> = status
+        .changed()
+        .filter(|&file| files.binary_search(&file).is_ok())
+        .cloned()
+        .collect();
+    if !dirty.is_empty() {
+        return Err(Error::UncommittedChanges(dirty));
+    }
+
+    let mut merged_files: Vec<&PathBuf> = Vec::new();
+    let mut conflicts: Vec<PathBuf> = Vec::new();
+    let mut binary_conflicts: Vec<PathBuf> = Vec::new();
+    let algorithm = get_config(&path)?.diff_algorithm;
+
+    for file in files {
+        let destination = path.root().join(file);
+        match merge_file(
+            &path,
+            base_tree.get(file),
+            ours_tree.get(file),
+            theirs_tree.get(file),
+            (head.branch(), branch),
+            algorithm,
+        )? {
+            FileMerge::Resolved(Some(hash)) => {
+                restore_file(&path, &hash, &destination)?;
+                merged_files.push(file);
+            }
+            FileMerge::Resolved(None) => remove_file(&path, file)?,
+            FileMerge::Merged(lines) => {
+                write_lines(&destination, &lines)?;
+                merged_files.push(file);
+            }
+            FileMerge::Conflicted(lines) => {
+                write_lines(&destination, &lines)?;
+                output.add(format!("CONFLICT: {}", file.display()));
+                conflicts.push(file.to_owned());
+                merged_files.push(file);
+            }
+            FileMerge::Unmergeable(hash) => {
+                restore_file(&path, &hash, &destination)?;
+                output.add(format!("CONFLICT (binary): {}", file.display()));
+                binary_conflicts.push(file.to_owned());
+                merged_files.push(file);
+            }
+        }
+    }
+
+    track_files(provided_path.clone(), merged_files)?;
+
+    let state = MergeState {
+        theirs,
+        message: message
+            .to_owned()
+            .unwrap_or_else(|| format!("Merge branch {} into {}", branch, head.branch())),
+        conflicts,
+        binary_conflicts,
+    };
+    set_merge_state(&path, Some(&state))?;
+
+    if state.conflicts.is_empty() && state.binary_conflicts.is_empty() {
+        snapshot(&None, &None, provided_path)?;
+        output.add(format!("Merged {} into {}", branch, head.branch()));
+    } else {
+        if !state.binary_conflicts.is_empty() {
+            output.add_str(
+                "Binary files keep this branch's version. Replace them if needed, then `kifi track` or `kifi rm` them.",
+            );
+        }
+        output.add_str("Fix the conflicts, then run `kifi klick` to finish the merge.");
+    }
+
+    Ok(())
+}
+
+/// Lists tags, or creates or deletes one
+/// A tag is annotated when a message is given.
+pub fn tag(
+    output: &mut dyn Output,
+    name: &Option<String>,
+    snapshot: &Option<String>,
+    message: &Option<String>,
+    delete: &bool,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    let mut tags = get_tags(&path)?;
+
+    let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
+    let snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+
+    match name {
+        Some(name) if *delete => {
+            let tag = tags.delete(name)?;
+            output.add(format!("Deleted tag {} (was {})", name, tag.snapshot));
+        }
+        Some(name) => {
+            check_tag_name(&snapshots, name)?;
+            let target = match snapshot {
+                Some(snapshot) => find_snapshot(&path, &snapshots, snapshot)?,
+                None => snapshots.get_head(get_head_snapshot(&path)?.as_ref())?,
+            };
+
+            let tag = match message {
+                Some(message) => Tag::new(&target.name, Some((&get_user()?, message.to_owned()))),
+                None => Tag::new(&target.name, None),
+            };
+            tags.create(name, tag)?;
+            output.add(format!("Tagged {} as {}", target.name, name));
+        }
+        None => {
+            for (name, tag) in tags.iter() {
+                match snapshots.get(&tag.snapshot) {
+                    Some(snap) => output.add(format!(
+                        "{}\t{} | {} - {} | {}",
+                        name,
+                        snap.name,
+                        snap.author,
+                        snap.author_email,
+                        format_time(snap.created)?
+                    )),
+                    None => output.add(format!("{}\t{}", name, tag.snapshot)),
+                }
+                if let Some(annotation) = &tag.annotation {
+                    output.add(format!(
+                        "    Tagged by {} - {} on {} UTC",
+                        annotation.tagger,
+                        annotation.tagger_email,
+                        format_time(annotation.created)?
+                    ));
+                    for line in annotation.message.lines() {
+                        output.add(format!("    {}", line));
+                    }
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    set_tags(&path, &tags)
+}
+
+/// Writes a patch file for each snapshot in a range such as `v1.2..HEAD`, oldest first
+/// The range includes the snapshots after `from`, up to and including `to`, which is HEAD if it isn't given.
+pub fn format_patch(
+    output: &mut dyn Output,
+    range: &str,
+    directory: &Option<PathBuf>,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+
+    let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
+    let snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+
+    let (from, to) = range.split_once("..").unwrap_or((range, ""));
+    let from = find_snapshot(&path, &snapshots, from)?;
+    let to = match to {
+        "" => snapshots.get_head(get_head_snapshot(&path)?.as_ref())?,
+        name => find_snapshot(&path, &snapshots, name)?,
+    };
+
+    let excluded: HashSet<&str> = snapshots
+        .ancestry(&from.name)
+        .into_iter()
+        .map(|snap| snap.name.as_str())
+        .collect();
+    // Merges are left out, since their changes are already in the patches for both branches.
+    let mut series: Vec<&Snapshot> = snapshots
+        .ancestry(&to.name)
+        .into_iter()
+        .filter(|snap| !excluded.contains(snap.name.as_str()) && snap.parents.len() < 2)
+        .collect();
+    series.reverse();
 
-    let mut config = config_local_dir().ok_or_else(|| Error::InvalidConfigDir)?;
-    config.push("kifi");
-    fs::create_dir_all(&config).map_err(Error::CreateDirectory)?;
-    config.push(".kificonfig");
-    let config_file = fs::File::create(config).map_err(Error::CreateFile)?;
-    to_writer(config_file, &user).map_err(Error::CBORWriter)?;
+    let algorithm = get_config(&path)?.diff_algorithm;
+    let mut patches: Vec<(&Snapshot, Vec<String>)> = Vec::new();
+    for snap in series {
+        let old_tree = match snap.parents.first() {
+            Some(parent) => read_tree(&path, &find_snapshot(&path, &snapshots, parent)?.tree)?,
+            None => Tree::new(),
+        };
+        let new_tree = read_tree(&path, &snap.tree)?;
+
+        let mut body = DebugOutput::new();
+        for options in [
+            DiffOptions {
+                summary: Some(Summary::Stat),
+                ..Default::default()
+            },
+            DiffOptions {
+                unified: Some(3),
+                ..Default::default()
+            },
+        ] {
+            diff_sources(
+                &path,
+                &Source::Snapshot(&old_tree),
+                &Source::Snapshot(&new_tree),
+                &[],
+                &options,
+                algorithm,
+                &mut body,
+            )?;
+            body.add_str("");
+        }
+
+        // Snapshots that don't change any files would give empty patches.
+        let body = body.print().unwrap_or_default();
+        if body.iter().any(|line| !line.is_empty()) {
+            patches.push((snap, body));
+        }
+    }
+
+    let directory = directory.clone().unwrap_or_else(|| PathBuf::from("."));
+    fs::create_dir_all(&directory).map_err(Error::CreateDirectory)?;
+
+    let total = patches.len();
+    for (number, (snap, body)) in patches.into_iter().enumerate() {
+        let mut lines = patch_header(snap, number + 1, total)?;
+        lines.extend(body);
+
+        let file = directory.join(patch_file_name(
+            number + 1,
+            snap.message.lines().next().unwrap_or_default(),
+        ));
+        fs::write(&file, lines.join("\n")).map_err(Error::CreateFile)?;
+        output.add(file.display().to_string());
+    }
 
     Ok(())
 }
+
+/// Applies patch files to the working tree, moving hunks or ignoring some of their context when needed
+/// Hunks that can't be applied are saved to a `.rej` file next to the file they change.
+pub fn apply(
+    output: &mut dyn Output,
+    patch_files: &[PathBuf],
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+
+    // Every patch is read first, so that none are applied if any of them can't be read.
+    let mut patches = Vec::new();
+    for patch_file in patch_files {
+        let contents = fs::read(patch_file).map_err(Error::ReadFile)?;
+        patches.push(parse_patch(&contents)?);
+    }
+
+    let mut changed: Vec<PathBuf> = Vec::new();
+    let mut deleted: Vec<PathBuf> = Vec::new();
+    let mut rejects: Vec<PathBuf> = Vec::new();
+    for patch in &patches {
+        if let Some(subject) = &patch.subject {
+            output.add(format!("Applying: {}", subject));
+        }
+
+        for file in &patch.files {
+            if let Some(reject) = apply_file(&path.root(), file, output)? {
+                rejects.push(reject);
+            }
+            match path.root().join(file.path()).is_file() {
+                true if file.new.is_some() => changed.push(file.path().to_owned()),
+                false if file.new.is_none() => deleted.push(file.path().to_owned()),
+                _ => (),
+            }
+        }
+    }
+
+    // Files created by the patches are tracked, so the next snapshot includes them. They are added
+    // even if they are ignored, since the cache has no entries inside ignored directories to change.
+    update_file_cache(provided_path)?;
+    let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
+    let mut cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
+    for file in changed {
+        cache.add_file_from_existing(file, FileStatus::Tracked);
+    }
+    for file in deleted {
+        if cache.get_status(&file).is_some() {
+            cache.remove_file(&file)?;
+        }
+    }
+    let cache_file = fs::File::create(path.filecache()).map_err(Error::CreateFile)?;
+    to_writer(cache_file, &cache).map_err(Error::CBORWriter)?;
+
+    if !rejects.is_empty() {
+        output.add_str("Some changes could not be applied, and were saved to:");
+        for reject in rejects {
+            output.add(format!("\t{}", reject.display()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Shows or changes the repository's settings
+/// With no key, every setting is listed. With a key and no value, that setting is shown.
+pub fn config(
+    output: &mut dyn Output,
+    key: &Option<String>,
+    value: &Option<String>,
+    unset: &bool,
+    global: &bool,
+    provided_path: Option<PathBuf>,
+) -> Result<(), Error> {
+    if *global {
+        return user_config(output, key, value, unset);
+    }
+
+    let path = get_kifi(&provided_path)?;
+    let mut config = get_config(&path)?;
+
+    let key = match key {
+        Some(key) => key,
+        None => {
+            for key in Config::KEYS {
+                output.add(format!("{}={}", key, config.get(key)?));
+            }
+            return Ok(());
+        }
+    };
+
+    match value {
+        _ if *unset => config.unset(key)?,
+        Some(value) => config.set(key, value)?,
+        None => {
+            output.add(config.get(key)?);
+            return Ok(());
+        }
+    }
+
+    set_config(&path, &config)
+}
+
+/// Shows or changes the user's settings, which apply to every repository
+fn user_config(
+    output: &mut dyn Output,
+    key: &Option<String>,
+    value: &Option<String>,
+    unset: &bool,
+) -> Result<(), Error> {
+    let mut user = get_user()?;
+
+    let key = match key {
+        Some(key) => key,
+        None => {
+            for key in User::KEYS {
+                output.add(format!("{}={}", key, get_user_setting(&user, key)?));
+            }
+            return Ok(());
+        }
+    };
+
+    match value {
+        _ if *unset => user.unset(key)?,
+        Some(value) => user.set(key, value)?,
+        None => {
+            output.add(get_user_setting(&user, key)?);
+            return Ok(());
+        }
+    }
+
+    set_user(&user)
+}
+
+/// Register a user, to reflect them as the author in later commits
+/// Registering again keeps the global ignore file that was chosen, unless another is given.
+pub fn register(name: &String, email: &String, kignore: &Option<PathBuf>) -> Result<(), Error> {
+    let mut user = User::new(name, email)?;
+
+    match kignore {
+        Some(kignore) => user.set_kignore(Some(kignore))?,
+        None => {
+            if let Ok(registered) = get_user() {
+                user.set_kignore(registered.global_kignore().as_deref())?;
+            }
+        }
+    }
+
+    set_user(&user)
+}
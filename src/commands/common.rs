@@ -1,15 +1,100 @@
 use super::metafiles::User;
-use crate::commands::metafiles::Paths;
+use crate::commands::metafiles::{
+    Branches, Config, Head, MergeState, Paths, Snapshot, Snapshots, Tags, Tree,
+};
+use crate::commands::objects::{write_object, write_tree};
 use crate::errors::Error;
 use dirs::config_local_dir;
-use serde_cbor::from_reader;
+use serde_cbor::{from_reader, to_writer};
 use std::{
-    fs,
+    env, fs,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 /// Checks if a repository already exists in the current working directory
+/// Repositories made by older versions of kifi are upgraded the first time they are found.
 pub fn get_kifi(provided_path: &Option<PathBuf>) -> Result<Paths, Error> {
+    let path = find_kifi(provided_path)?;
+
+    // Older versions of kifi had no branches, so a missing HEAD means the repository needs upgrading.
+    if !path.head().is_file() && path.snaps().is_file() {
+        upgrade_repository(&path)?;
+    }
+
+    Ok(path)
+}
+
+/// Upgrades a repository made by an older version of kifi
+/// Those versions copied each snapshot's files into a directory named after it, instead of storing them
+/// as objects, and had a single line of history without branches or tags.
+fn upgrade_repository(path: &Paths) -> Result<(), Error> {
+    let snapshots_file = fs::read(path.snaps()).map_err(Error::ReadFile)?;
+    let mut snapshots: Snapshots = from_reader(&snapshots_file[..]).map_err(Error::CBORReader)?;
+
+    let mut legacy_directories: Vec<PathBuf> = Vec::new();
+    let upgraded = snapshots.upgrade(|snap| {
+        let directory = path.kifi().join(&snap.name);
+        let tree = store_legacy_snapshot(path, &directory)?;
+        legacy_directories.push(directory);
+        Ok(tree)
+    })?;
+    if upgraded {
+        let snapshots_file = fs::File::create(path.snaps()).map_err(Error::CreateFile)?;
+        to_writer(snapshots_file, &snapshots).map_err(Error::CBORWriter)?;
+    }
+
+    // The copies are only removed once the snapshots point at their trees, so no files are lost.
+    for directory in legacy_directories {
+        if directory.is_dir() && directory != path.objects() {
+            fs::remove_dir_all(&directory).map_err(Error::RemoveFile)?;
+        }
+    }
+
+    if !path.tags().is_file() {
+        set_tags(path, &Tags::new())?;
+    }
+    let head = Head::new();
+    let mut branches = Branches::new();
+    if let Some(latest) = snapshots.latest() {
+        branches.set(head.branch(), &latest.name);
+    }
+    set_branches(path, &branches)?;
+    // HEAD is written last, so an interrupted upgrade is tried again.
+    set_head(path, &head)
+}
+
+/// Stores the files an older version of kifi copied into a snapshot's directory, returning their tree's hash
+/// A missing directory gives an empty tree.
+fn store_legacy_snapshot(path: &Paths, directory: &Path) -> Result<String, Error> {
+    let mut tree = Tree::new();
+    let mut directories = vec![directory.to_path_buf()];
+
+    while let Some(current) = directories.pop() {
+        let Ok(entries) = fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in entries {
+            let file = entry.map_err(Error::ReadFile)?.path();
+            if file.is_dir() {
+                directories.push(file);
+                continue;
+            }
+
+            let contents = fs::read(&file).map_err(Error::ReadFile)?;
+            let file_path = file
+                .strip_prefix(directory)
+                .expect("Files found here are inside the snapshot's directory.")
+                .to_path_buf();
+            tree.insert(file_path, write_object(path, &contents)?);
+        }
+    }
+
+    write_tree(path, &tree)
+}
+
+/// Looks for a repository in the given directory or any of its parents
+fn find_kifi(provided_path: &Option<PathBuf>) -> Result<Paths, Error> {
     let provided_path = match provided_path {
         Some(p) => {
             if p.ends_with(".kifi") {
@@ -53,11 +138,224 @@ pub fn get_kifi(provided_path: &Option<PathBuf>) -> Result<Paths, Error> {
     Err(Error::KifiNotInitialised)
 }
 
+/// Name of the global ignore file in kifi's config directory, used unless the user chose another
+const GLOBAL_KIGNORE: &str = "ignore";
+
+/// Overrides the directory holding the user's settings, so they can be kept apart, as in tests
+const CONFIG_DIR_VARIABLE: &str = "KIFI_CONFIG_DIR";
+
+/// Directory holding the user's details and settings, shared by every repository
+fn user_config_dir() -> Result<PathBuf, Error> {
+    if let Some(config) = env::var_os(CONFIG_DIR_VARIABLE).filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(config));
+    }
+
+    let mut config = config_local_dir().ok_or(Error::InvalidConfigDir)?;
+    config.push("kifi");
+    Ok(config)
+}
+
 /// Get user data
 pub fn get_user() -> Result<User, Error> {
-    let mut config = config_local_dir().ok_or_else(|| Error::InvalidConfigDir)?;
-    config.push("kifi");
-    config.push(".kificonfig");
+    let config = user_config_dir()?.join(".kificonfig");
     let config_file = fs::read(config).map_err(|_| Error::UserNotRegistered)?;
     from_reader(&config_file[..]).map_err(Error::CBORReader)
 }
+
+/// Writes user data, replacing the registered user
+pub fn set_user(user: &User) -> Result<(), Error> {
+    let config = user_config_dir()?;
+    fs::create_dir_all(&config).map_err(Error::CreateDirectory)?;
+    let config_file = fs::File::create(config.join(".kificonfig")).map_err(Error::CreateFile)?;
+    to_writer(config_file, user).map_err(Error::CBORWriter)
+}
+
+/// Returns the default global ignore file, which applies to every repository
+pub fn default_global_kignore() -> Result<PathBuf, Error> {
+    Ok(user_config_dir()?.join(GLOBAL_KIGNORE))
+}
+
+/// Returns the global ignore file that applies to every repository, the user's choice or the default
+/// The default is used when no user is registered.
+pub fn global_kignore() -> Result<PathBuf, Error> {
+    match get_user().ok().and_then(|user| user.global_kignore()) {
+        Some(kignore) => Ok(kignore),
+        None => default_global_kignore(),
+    }
+}
+
+/// Returns one of the user's settings, or its default if it wasn't changed
+pub fn get_user_setting(user: &User, key: &str) -> Result<String, Error> {
+    match (user.get(key)?, key) {
+        (Some(value), _) => Ok(value),
+        (None, "user.kignore") => Ok(default_global_kignore()?.display().to_string()),
+        (None, _) => Err(Error::UnknownConfigKey(key.to_string())),
+    }
+}
+
+/// Reads HEAD from the repository, this is the current branch
+pub fn get_head(path: &Paths) -> Result<Head, Error> {
+    let head_file = fs::read(path.head()).map_err(Error::ReadFile)?;
+    from_reader(&head_file[..]).map_err(Error::CBORReader)
+}
+
+/// Writes HEAD to the repository
+pub fn set_head(path: &Paths, head: &Head) -> Result<(), Error> {
+    let head_file = fs::File::create(path.head()).map_err(Error::CreateFile)?;
+    to_writer(head_file, head).map_err(Error::CBORWriter)
+}
+
+/// Reads branches from the repository
+pub fn get_branches(path: &Paths) -> Result<Branches, Error> {
+    let branches_file = fs::read(path.branches()).map_err(Error::ReadFile)?;
+    from_reader(&branches_file[..]).map_err(Error::CBORReader)
+}
+
+/// Writes branches to the repository
+pub fn set_branches(path: &Paths, branches: &Branches) -> Result<(), Error> {
+    let branches_file = fs::File::create(path.branches()).map_err(Error::CreateFile)?;
+    to_writer(branches_file, branches).map_err(Error::CBORWriter)
+}
+
+/// Gets the name of the snapshot the current branch points to
+/// This is `None` until a snapshot is taken on the current branch.
+pub fn get_head_snapshot(path: &Paths) -> Result<Option<String>, Error> {
+    let head = get_head(path)?;
+    let branches = get_branches(path)?;
+    Ok(branches.get(head.branch()).cloned())
+}
+
+/// Points the current branch at a new snapshot
+pub fn advance_head(path: &Paths, snapshot: &str) -> Result<(), Error> {
+    let head = get_head(path)?;
+    let mut branches = get_branches(path)?;
+    branches.set(head.branch(), snapshot);
+    set_branches(path, &branches)
+}
+
+/// Reads the merge in progress, if there is one
+pub fn get_merge_state(path: &Paths) -> Result<Option<MergeState>, Error> {
+    if !path.merge().is_file() {
+        return Ok(None);
+    }
+
+    let merge_file = fs::read(path.merge()).map_err(Error::ReadFile)?;
+    from_reader(&merge_file[..])
+        .map(Some)
+        .map_err(Error::CBORReader)
+}
+
+/// Records a merge in progress, or clears it when `None` is given
+pub fn set_merge_state(path: &Paths, state: Option<&MergeState>) -> Result<(), Error> {
+    match state {
+        Some(state) => {
+            let merge_file = fs::File::create(path.merge()).map_err(Error::CreateFile)?;
+            to_writer(merge_file, state).map_err(Error::CBORWriter)
+        }
+        None => match fs::remove_file(path.merge()) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::RemoveFile(e)),
+        },
+    }
+}
+
+/// Marks binary conflicts in a merge in progress as resolved, once the user tracked or removed them
+pub fn resolve_conflicts(path: &Paths, files: &[PathBuf]) -> Result<(), Error> {
+    if let Some(mut state) = get_merge_state(path)? {
+        if state.resolve(files) {
+            set_merge_state(path, Some(&state))?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the repository's settings, repositories without a settings file use the defaults
+pub fn get_config(path: &Paths) -> Result<Config, Error> {
+    if !path.config().is_file() {
+        return Ok(Config::default());
+    }
+
+    let config_file = fs::read(path.config()).map_err(Error::ReadFile)?;
+    from_reader(&config_file[..]).map_err(Error::CBORReader)
+}
+
+pub fn set_config(path: &Paths, config: &Config) -> Result<(), Error> {
+    let config_file = fs::File::create(path.config()).map_err(Error::CreateFile)?;
+    to_writer(config_file, config).map_err(Error::CBORWriter)
+}
+
+/// Reads tags from the repository
+pub fn get_tags(path: &Paths) -> Result<Tags, Error> {
+    let tags_file = fs::read(path.tags()).map_err(Error::ReadFile)?;
+    from_reader(&tags_file[..]).map_err(Error::CBORReader)
+}
+
+/// Writes tags to the repository
+pub fn set_tags(path: &Paths, tags: &Tags) -> Result<(), Error> {
+    let tags_file = fs::File::create(path.tags()).map_err(Error::CreateFile)?;
+    to_writer(tags_file, tags).map_err(Error::CBORWriter)
+}
+
+/// Turns a tag, a branch or `HEAD` into the name of the snapshot it points to
+/// Names of snapshots take precedence, then tags, then branches. Anything else is returned as it is.
+pub fn resolve_name(path: &Paths, snapshots: &Snapshots, name: &str) -> Result<String, Error> {
+    if snapshots.contains(name) {
+        return Ok(name.to_owned());
+    }
+
+    if let Some(tag) = get_tags(path)?.get(name) {
+        return Ok(tag.snapshot.to_owned());
+    }
+
+    if let Some(snapshot) = get_branches(path)?.get(name) {
+        return Ok(snapshot.to_owned());
+    }
+
+    if name == "HEAD" {
+        if let Some(snapshot) = get_head_snapshot(path)? {
+            return Ok(snapshot);
+        }
+    }
+
+    Ok(name.to_owned())
+}
+
+/// Finds a snapshot by its exact name, or by a tag or branch pointing to it
+pub fn find_snapshot<'a>(
+    path: &Paths,
+    snapshots: &'a Snapshots,
+    name: &str,
+) -> Result<&'a Snapshot, Error> {
+    let name = resolve_name(path, snapshots, name)?;
+    snapshots
+        .get(&name)
+        .ok_or_else(|| Error::SnapshotNotFound(name.to_owned()))
+}
+
+/// Checks that a new tag's name can be told apart from snapshots and ranges wherever names are accepted
+pub fn check_tag_name(snapshots: &Snapshots, name: &str) -> Result<(), Error> {
+    let reason = if name.trim().is_empty() {
+        "tags need a name"
+    } else if name.contains("..") {
+        "'..' separates the ends of a range"
+    } else if name == "HEAD" {
+        "HEAD refers to the current snapshot"
+    } else if snapshots.contains(name) {
+        "a snapshot already has that name"
+    } else {
+        return Ok(());
+    };
+
+    Err(Error::InvalidTagName(name.to_owned(), reason))
+}
+
+/// Formats a time as UTC, the way it is shown to users
+pub fn format_time(time: SystemTime) -> Result<String, Error> {
+    time::OffsetDateTime::from(time)
+        .format(
+            &time::format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]")
+                .map_err(|_| Error::InvalidTime(time))?,
+        )
+        .map_err(|_| Error::InvalidTime(time))
+}
@@ -0,0 +1,326 @@
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the file listing patterns for files that shouldn't be tracked
+pub const KIGNORE: &str = ".kignore";
+
+/// `*` and `?` never match `/`, as in `.gitignore` files
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Source given to kifi's own patterns, which aren't read from a file
+pub const BUILT_IN: &str = "<built-in>";
+
+/// A line from an ignore file, following the rules for `.gitignore` files
+#[derive(Clone)]
+pub struct Rule {
+    /// The pattern as it was written, without trailing spaces
+    pub text: String,
+    /// Ignore file the pattern was read from
+    pub source: PathBuf,
+    /// Line of the ignore file the pattern was read from, counting from 1
+    pub line: usize,
+    pattern: Pattern,
+    /// Patterns starting with `!` include files that earlier patterns ignored
+    pub negated: bool,
+    /// Patterns ending with `/` only match directories, and so everything inside them
+    directory_only: bool,
+    /// Patterns with a `/` before their end are matched against the whole path instead of just the name
+    anchored: bool,
+    /// Directory containing the ignore file, relative to the repository's root
+    base: PathBuf,
+}
+
+impl Rule {
+    /// Reads a rule from a line, `None` if the line is blank, a comment, or not a valid pattern
+    fn parse(line: &str, base: &Path, source: &Path, number: usize) -> Option<Rule> {
+        let line = trim_trailing_spaces(line);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let text = line.to_string();
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (directory_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Rule {
+            text,
+            source: source.to_owned(),
+            line: number,
+            pattern: Pattern::new(&to_glob(line)).ok()?,
+            negated,
+            directory_only,
+            anchored,
+            base: base.to_owned(),
+        })
+    }
+
+    /// Checks whether the rule applies to a path relative to the repository's root
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        let Ok(path) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+
+        match self.anchored {
+            true => self.pattern.matches_with(&to_slashes(path), MATCH_OPTIONS),
+            false => path.file_name().is_some_and(|name| {
+                self.pattern
+                    .matches_with(&name.to_string_lossy(), MATCH_OPTIONS)
+            }),
+        }
+    }
+}
+
+/// Removes spaces at the end of a line, unless they are escaped with a backslash
+fn trim_trailing_spaces(line: &str) -> &str {
+    let trimmed = line.trim_end_matches(' ');
+    match trimmed.ends_with('\\') && trimmed.len() < line.len() {
+        true => &line[..trimmed.len() + 1],
+        false => trimmed,
+    }
+}
+
+/// Turns a pattern from an ignore file into one `glob` understands
+/// Backslashes escape the character after them, and `**` is only special as a whole path component.
+fn to_glob(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut glob = String::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => {
+                if let Some(escaped) = chars.get(index + 1) {
+                    glob.push_str(&Pattern::escape(&escaped.to_string()));
+                }
+                index += 2;
+            }
+            '*' => {
+                let stars = chars[index..].iter().take_while(|&&c| c == '*').count();
+                let starts_component = index == 0 || chars[index - 1] == '/';
+                let ends_component = chars.get(index + stars).is_none_or(|&c| c == '/');
+                match stars > 1 && starts_component && ends_component {
+                    true => glob.push_str("**"),
+                    false => glob.push('*'),
+                }
+                index += stars;
+            }
+            c => {
+                glob.push(c);
+                index += 1;
+            }
+        }
+    }
+
+    glob
+}
+
+/// Joins a path's components with `/`, the separator used in patterns on every platform
+fn to_slashes(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Patterns from ignore files, where later patterns take precedence over earlier ones
+#[derive(Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    pub fn new() -> Self {
+        IgnoreRules { rules: Vec::new() }
+    }
+
+    /// Adds the patterns in an ignore file, which apply to paths inside `base`
+    pub fn add_rules(&mut self, contents: &str, base: &Path, source: &Path) {
+        self.rules.extend(
+            contents
+                .lines()
+                .enumerate()
+                .filter_map(|(index, line)| Rule::parse(line, base, source, index + 1)),
+        );
+    }
+
+    /// Adds the patterns in an ignore file if it can be read, skipping it otherwise
+    pub fn add_file(&mut self, file: &Path, base: &Path) {
+        if let Ok(contents) = fs::read(file) {
+            self.add_rules(&String::from_utf8_lossy(&contents), base, file);
+        }
+    }
+
+    /// Adds the ignore files in the directories containing a path, as searching the working tree would
+    /// Directories inside ignored directories aren't searched, so their ignore files are skipped.
+    pub fn add_parent_files(&mut self, root: &Path, path: &Path) {
+        let mut directories: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .collect();
+        directories.reverse();
+
+        for directory in directories {
+            if self.is_ignored(directory, true) {
+                break;
+            }
+            self.add_file(&root.join(directory).join(KIGNORE), directory);
+        }
+    }
+
+    /// Returns the last rule matching a path, which decides whether it is ignored
+    fn last_match(&self, path: &Path, is_dir: bool) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+    }
+
+    /// Returns the rule that decides whether a path is ignored, if any pattern matches it
+    /// A rule ignoring a parent directory decides for everything inside it, since negated patterns
+    /// can't include files inside an ignored directory again.
+    pub fn deciding_rule(&self, path: &Path, is_dir: bool) -> Option<&Rule> {
+        let mut parents: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .collect();
+        parents.reverse();
+
+        parents
+            .into_iter()
+            .filter_map(|parent| self.last_match(parent, true))
+            .find(|rule| !rule.negated)
+            .or_else(|| self.last_match(path, is_dir))
+    }
+
+    /// Checks whether a path, relative to the repository's root, is ignored
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.deciding_rule(path, is_dir)
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IgnoreRules, KIGNORE};
+    use std::path::Path;
+
+    fn rules(contents: &str) -> IgnoreRules {
+        let mut rules = IgnoreRules::new();
+        rules.add_rules(contents, Path::new(""), Path::new(KIGNORE));
+        rules
+    }
+
+    fn ignored(rules: &IgnoreRules, path: &str) -> bool {
+        rules.is_ignored(Path::new(path), false)
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let rules = rules("# build output\n\n   \n\\#notes\nkeep.txt   \ntrailing\\ \n");
+
+        assert!(!ignored(&rules, "# build output"));
+        assert!(ignored(&rules, "#notes"));
+        assert!(ignored(&rules, "keep.txt"));
+        assert!(ignored(&rules, "trailing "));
+        assert!(!ignored(&rules, "trailing"));
+    }
+
+    #[test]
+    fn test_negation_and_last_match() {
+        let rules = rules("*.log\n!important.log\n\\!bang\n");
+
+        assert!(ignored(&rules, "server.log"));
+        assert!(ignored(&rules, "src/server.log"));
+        assert!(!ignored(&rules, "important.log"));
+        assert!(!ignored(&rules, "src/important.log"));
+        assert!(ignored(&rules, "!bang"));
+
+        // A later pattern ignores the file again.
+        let rules = self::rules("*.log\n!important.log\nimportant.log\n");
+        assert!(ignored(&rules, "important.log"));
+    }
+
+    #[test]
+    fn test_directory_patterns() {
+        let rules = rules("build/\n!build/keep.txt\n");
+
+        assert!(!ignored(&rules, "build"));
+        assert!(rules.is_ignored(Path::new("build"), true));
+        assert!(ignored(&rules, "build/output.o"));
+        assert!(ignored(&rules, "src/build/output.o"));
+        // Files inside an ignored directory can't be included again.
+        assert!(ignored(&rules, "build/keep.txt"));
+    }
+
+    #[test]
+    fn test_anchored_patterns() {
+        let rules = rules("/todo.txt\ndoc/*.html\n");
+
+        assert!(ignored(&rules, "todo.txt"));
+        assert!(!ignored(&rules, "src/todo.txt"));
+        assert!(ignored(&rules, "doc/index.html"));
+        assert!(!ignored(&rules, "doc/api/index.html"));
+        assert!(!ignored(&rules, "src/doc/index.html"));
+    }
+
+    #[test]
+    fn test_double_asterisks() {
+        let rules = rules("**/cache\nvendor/**\na/**/z\nfoo**bar\n");
+
+        assert!(ignored(&rules, "cache"));
+        assert!(ignored(&rules, "src/deep/cache"));
+        assert!(ignored(&rules, "vendor/lib/code.rs"));
+        assert!(!ignored(&rules, "vendor"));
+        assert!(ignored(&rules, "a/z"));
+        assert!(ignored(&rules, "a/b/c/z"));
+        // `**` that isn't a whole path component acts like `*`.
+        assert!(ignored(&rules, "fooxbar"));
+        assert!(!ignored(&rules, "foo/bar"));
+    }
+    #[test]
+    fn test_nested_rules() {
+        let mut rules = rules("*.tmp\n/build\n");
+        rules.add_rules(
+            "!*.tmp\n/build\nlocal/\n",
+            Path::new("sub"),
+            Path::new("sub/.kignore"),
+        );
+
+        // The deeper file takes precedence, but only inside its own directory.
+        assert!(ignored(&rules, "a.tmp"));
+        assert!(!ignored(&rules, "sub/a.tmp"));
+        assert!(!ignored(&rules, "sub/deep/a.tmp"));
+
+        // Anchored patterns start from the directory containing the ignore file.
+        assert!(ignored(&rules, "build"));
+        assert!(ignored(&rules, "sub/build"));
+        assert!(!ignored(&rules, "sub/deep/build"));
+
+        assert!(!ignored(&rules, "local/notes.txt"));
+        assert!(ignored(&rules, "sub/local/notes.txt"));
+    }
+}
@@ -1,16 +1,17 @@
-use crate::commands::{get_kifi, FileCache};
+use crate::commands::ignore::{IgnoreRules, BUILT_IN, KIGNORE};
+use crate::commands::{get_kifi, FileCache, Paths};
 use crate::errors::Error;
-use glob::Pattern;
 use serde_cbor::{from_reader, to_writer};
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use super::common::get_user;
+use super::common::global_kignore;
 
 /// Generates a vector of files and stores it
 pub fn update_file_cache(provided_path: Option<PathBuf>) -> Result<(), Error> {
     let path = get_kifi(&provided_path)?;
-    let kignore = get_kignore(path.root());
+    let mut kignore = get_kignore(path.root());
 
     let old_file_list = match fs::metadata(path.filecache()) {
         Ok(metadata) => {
@@ -25,6 +26,7 @@ pub fn update_file_cache(provided_path: Option<PathBuf>) -> Result<(), Error> {
     };
 
     let mut file_list = FileCache::new();
+    let tracked_directories = old_file_list.tracked_directories();
 
     match fs::read_dir(path.root()).map_err(Error::GetCurrentDirectory) {
         Ok(files) => {
@@ -34,8 +36,10 @@ pub fn update_file_cache(provided_path: Option<PathBuf>) -> Result<(), Error> {
                         f,
                         &mut file_list,
                         &old_file_list,
+                        &tracked_directories,
                         &path.root(),
-                        &kignore,
+                        &mut kignore,
+                        false,
                     )?,
                     Err(e) => return Err(Error::ReadFile(e)),
                 }
@@ -52,53 +56,118 @@ pub fn update_file_cache(provided_path: Option<PathBuf>) -> Result<(), Error> {
     Ok(())
 }
 
-/// Get ignore patterns
-fn get_kignore(root: PathBuf) -> Vec<Pattern> {
-    let mut kignore: Vec<Pattern> = Vec::new();
+/// Marks the given files as tracked, after updating the cache
+pub fn track_files(provided_path: Option<PathBuf>, files: Vec<&PathBuf>) -> Result<(), Error> {
+    let path = get_kifi(&provided_path)?;
+    update_file_cache(provided_path)?;
 
-    if let Ok(local_patterns) = fs::read(root.join(".kignore")) {
-        let local_patterns_cow = String::from_utf8_lossy(&local_patterns[..]);
-        let local_patterns_strings = local_patterns_cow.lines().map(String::from);
-        let local_glob_result = local_patterns_strings.map(|s| Pattern::new(&s));
-        let mut local_glob_patterns = local_glob_result.filter_map(|p| p.ok()).collect();
+    let cache_file = fs::read(path.filecache()).map_err(Error::ReadFile)?;
+    let mut cache: FileCache = from_reader(&cache_file[..]).map_err(Error::CBORReader)?;
 
-        kignore.append(&mut local_glob_patterns);
+    // Files inside ignored directories aren't in the cache, so entries are added rather than changed.
+    for file in files {
+        cache.add_file_from_existing(file.to_owned(), super::metafiles::FileStatus::Tracked);
     }
 
-    if let Ok(user) = get_user() {
-        if let Some(global_kignore) = user.kignore() {
-            if let Ok(global_patterns) = fs::read(global_kignore).map_err(Error::ReadFile) {
-                let global_patterns_cow = String::from_utf8_lossy(&global_patterns[..]);
-                let global_patterns_strings = global_patterns_cow.lines().map(String::from);
-                let global_glob_result = global_patterns_strings.map(|s| Pattern::new(&s));
-                let mut global_glob_patterns = global_glob_result.filter_map(|p| p.ok()).collect();
+    let cache_file = fs::File::create(path.filecache()).map_err(Error::CreateFile)?;
+    to_writer(cache_file, &cache).map_err(Error::CBORWriter)
+}
 
-                kignore.append(&mut global_glob_patterns);
+/// Lists the files inside a directory, relative to the repository's root, without kifi's own files
+/// Ignore patterns aren't checked, so this finds files the cache leaves out.
+pub fn files_in(path: &Paths, directory: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut directories = vec![path.root().join(directory)];
+
+    while let Some(directory) = directories.pop() {
+        if directory == path.kifi() {
+            continue;
+        }
+        for entry in fs::read_dir(&directory).map_err(Error::GetCurrentDirectory)? {
+            let entry = entry.map_err(Error::ReadFile)?;
+            if entry.file_type().map_err(Error::ReadFile)?.is_dir() {
+                directories.push(entry.path());
+            } else {
+                let file = entry
+                    .path()
+                    .strip_prefix(path.root())
+                    .expect("Files listed here must be contained within root")
+                    .to_owned();
+                files.push(file);
             }
         }
     }
 
-    kignore.push(Pattern::new(".kifi/*").expect("This should be a valid pattern."));
+    Ok(files)
+}
+
+/// Get ignore patterns
+/// The global ignore file comes first, so the repository's own patterns take precedence over it.
+/// It is read even when no user is registered, from its default location.
+pub fn get_kignore(root: PathBuf) -> IgnoreRules {
+    let mut kignore = IgnoreRules::new();
+
+    if let Ok(global_kignore) = global_kignore() {
+        kignore.add_file(&global_kignore, Path::new(""));
+    }
+
+    kignore.add_file(&root.join(KIGNORE), Path::new(""));
+
+    // Added after the root's patterns, so that none of them can include kifi's own files.
+    // Patterns in nested ignore files only apply inside their own directories, so can't either.
+    kignore.add_rules("/.kifi/", Path::new(""), Path::new(BUILT_IN));
 
     kignore
 }
 
 /// Loops through files and adds them to the cache vector
+/// Ignore files found in directories are added to `kignore` before anything inside them is checked,
+/// and since they are added after the files of their parent directories, their patterns take precedence.
+/// Ignored directories aren't searched, except towards files inside them that were tracked anyway.
 fn get_name_from_fileentries(
     file: fs::DirEntry,
     file_list: &mut FileCache,
     old_file_list: &FileCache,
+    tracked_directories: &HashSet<&Path>,
     root: &PathBuf,
-    kignore: &Vec<Pattern>,
+    kignore: &mut IgnoreRules,
+    inside_ignored: bool,
 ) -> Result<(), Error> {
     if file.file_type().map_err(Error::ReadFile)?.is_dir() {
+        let directory = file
+            .path()
+            .strip_prefix(root)
+            .expect("Directories checked here must be contained within root")
+            .to_owned();
+        let ignored = inside_ignored || kignore.is_ignored(&directory, true);
+        if ignored && !tracked_directories.contains(directory.as_path()) {
+            // The directory is listed in place of its files, unless it is kifi's own or inside one already listed.
+            let built_in = kignore
+                .deciding_rule(&directory, true)
+                .is_some_and(|rule| rule.source == Path::new(BUILT_IN));
+            if !inside_ignored && !built_in {
+                file_list.add_ignored_directory(directory);
+            }
+            return Ok(());
+        }
+        // Patterns inside an ignored directory can't include anything in it again, so aren't read.
+        if !ignored {
+            kignore.add_file(&file.path().join(KIGNORE), &directory);
+        }
+
         match fs::read_dir(file.path()).map_err(Error::GetCurrentDirectory) {
             Ok(files) => {
                 for file in files {
                     match file {
-                        Ok(f) => {
-                            get_name_from_fileentries(f, file_list, old_file_list, root, kignore)?
-                        }
+                        Ok(f) => get_name_from_fileentries(
+                            f,
+                            file_list,
+                            old_file_list,
+                            tracked_directories,
+                            root,
+                            kignore,
+                            ignored,
+                        )?,
                         Err(e) => return Err(Error::ReadFile(e)),
                     }
                 }
@@ -115,9 +184,17 @@ fn get_name_from_fileentries(
             .expect("Files checked here must be contained within root")
             .to_owned();
 
-        if old_file_list.get_keys().contains(&file_path) {
-            file_list.add_file_from_existing(file_path.to_owned(), old_file_list.get_status(file_path).expect("Keys were fetched from the cache and immediately used, so the corresponding value should exist.").to_owned());
-        } else if file_is_ignored(file_path, kignore) {
+        if inside_ignored {
+            // Only the tracked files are kept, the rest of an ignored directory isn't listed.
+            if old_file_list.has_tracked_file(file_path) {
+                file_list.add_file_from_existing(
+                    file_path.to_owned(),
+                    super::metafiles::FileStatus::Tracked,
+                );
+            }
+        } else if let Some(status) = old_file_list.get_status(file_path) {
+            file_list.add_file_from_existing(file_path.to_owned(), status.to_owned());
+        } else if kignore.is_ignored(file_path, false) {
             file_list.add_file(file_path.to_owned(), super::metafiles::FileStatus::Ignored);
         } else {
             file_list.add_file(
@@ -129,13 +206,3 @@ fn get_name_from_fileentries(
 
     Ok(())
 }
-
-fn file_is_ignored(file: &Path, kignore: &Vec<Pattern>) -> bool {
-    for pattern in kignore {
-        if pattern.matches_path(file) {
-            return true;
-        }
-    }
-
-    false
-}
@@ -0,0 +1,220 @@
+use crate::commands::metafiles::Paths;
+use crate::commands::objects::read_object;
+use crate::commands::preview::{from_lines, is_binary, split_newline, to_lines, DiffAlgorithm};
+use crate::errors::Error;
+use std::fs;
+use std::path::Path;
+
+const CONFLICT_START: &str = "<<<<<<<";
+const CONFLICT_SEPARATOR: &str = "=======";
+const CONFLICT_END: &str = ">>>>>>>";
+
+/// Outcome of merging a single file
+pub enum FileMerge {
+    /// The file is taken unchanged from one side, by its hash, or deleted if `None`
+    Resolved(Option<String>),
+    /// Both sides changed the file, and the changes were combined
+    Merged(Vec<String>),
+    /// Both sides changed the same lines, conflicting hunks are surrounded by markers
+    Conflicted(Vec<String>),
+    /// Both sides changed a binary file, which can't be merged by lines
+    /// Ours is kept by its hash, or theirs if we deleted the file.
+    Unmergeable(String),
+}
+
+/// Merges a file given its hashes in the common ancestor and on both sides
+/// `None` means the file does not exist in that snapshot.
+pub fn merge_file(
+    path: &Paths,
+    base: Option<&String>,
+    ours: Option<&String>,
+    theirs: Option<&String>,
+    labels: (&str, &str),
+    algorithm: DiffAlgorithm,
+) -> Result<FileMerge, Error> {
+    if ours == theirs || base == theirs {
+        return Ok(FileMerge::Resolved(ours.cloned()));
+    }
+    if base == ours {
+        return Ok(FileMerge::Resolved(theirs.cloned()));
+    }
+
+    // Both sides changed the file. A deleted file is merged as if it were empty.
+    let read = |hash: Option<&String>| -> Result<Vec<u8>, Error> {
+        match hash {
+            Some(hash) => read_object(path, hash),
+            None => Ok(Vec::new()),
+        }
+    };
+    let contents = [read(base)?, read(ours)?, read(theirs)?];
+    if contents.iter().any(|contents| is_binary(contents)) {
+        let kept = ours
+            .or(theirs)
+            .expect("Files deleted on both sides were resolved above.");
+        return Ok(FileMerge::Unmergeable(kept.to_owned()));
+    }
+    let [base, ours, theirs] = contents.map(|contents| to_lines(&contents));
+
+    let (lines, conflicted) = merge_lines(&base, &ours, &theirs, labels, algorithm);
+
+    if conflicted {
+        Ok(FileMerge::Conflicted(lines))
+    } else {
+        Ok(FileMerge::Merged(lines))
+    }
+}
+
+/// Three-way merge of lines, returns the merged lines and whether any hunks conflicted
+pub fn merge_lines(
+    base: &[String],
+    ours: &[String],
+    theirs: &[String],
+    labels: (&str, &str),
+    algorithm: DiffAlgorithm,
+) -> (Vec<String>, bool) {
+    let ours_matches = algorithm.line_matches(base, ours);
+    let theirs_matches = algorithm.line_matches(base, theirs);
+
+    let mut merged: Vec<String> = Vec::new();
+    let mut conflicted = false;
+    let (mut b, mut o, mut t) = (0, 0, 0);
+
+    loop {
+        // Lines left unchanged on both sides are copied as they are.
+        let mut stable = 0;
+        while b + stable < base.len()
+            && ours_matches[b + stable] == Some(o + stable)
+            && theirs_matches[b + stable] == Some(t + stable)
+        {
+            stable += 1;
+        }
+        if stable > 0 {
+            merged.extend_from_slice(&base[b..b + stable]);
+            b += stable;
+            o += stable;
+            t += stable;
+            continue;
+        }
+
+        // Otherwise, the hunk extends up to the next line of the base that both sides kept.
+        let (b_end, o_end, t_end) = match (b..base.len())
+            .find(|&i| ours_matches[i].is_some() && theirs_matches[i].is_some())
+        {
+            Some(i) => (
+                i,
+                ours_matches[i].expect("The line was just checked to be matched."),
+                theirs_matches[i].expect("The line was just checked to be matched."),
+            ),
+            None => (base.len(), ours.len(), theirs.len()),
+        };
+
+        if (b_end, o_end, t_end) == (b, o, t) {
+            break;
+        }
+
+        let base_hunk = &base[b..b_end];
+        let ours_hunk = &ours[o..o_end];
+        let theirs_hunk = &theirs[t..t_end];
+
+        if ours_hunk == theirs_hunk || theirs_hunk == base_hunk {
+            merged.extend_from_slice(ours_hunk);
+        } else if ours_hunk == base_hunk {
+            merged.extend_from_slice(theirs_hunk);
+        } else {
+            conflicted = true;
+            merged.push(format!("{} {}", CONFLICT_START, labels.0));
+            merged.extend(ours_hunk.iter().map(|line| terminated(line)));
+            merged.push(CONFLICT_SEPARATOR.to_string());
+            merged.extend(theirs_hunk.iter().map(|line| terminated(line)));
+            merged.push(format!("{} {}", CONFLICT_END, labels.1));
+        }
+
+        b = b_end;
+        o = o_end;
+        t = t_end;
+    }
+
+    (merged, conflicted)
+}
+
+/// Ends a line with a newline, so that a conflict marker after it starts on its own line
+fn terminated(line: &str) -> String {
+    split_newline(line).0.to_string()
+}
+
+/// Writes merged lines to a file in the working tree
+pub fn write_lines(destination: &Path, lines: &[String]) -> Result<(), Error> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(Error::CreateDirectory)?;
+    }
+
+    fs::write(destination, from_lines(lines)).map_err(Error::CreateFile)
+}
+
+/// Checks whether any conflict markers are left in the given lines
+/// Only the lines starting and ending a conflict count, since `=======` alone is common in text.
+pub fn has_conflict_markers(lines: &[String]) -> bool {
+    lines.iter().any(|line| {
+        [CONFLICT_START, CONFLICT_END].iter().any(|marker| {
+            line.strip_prefix(marker)
+                .is_some_and(|rest| rest.starts_with(' '))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_lines;
+    use crate::commands::preview::DiffAlgorithm;
+
+    fn to_strings(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|&line| String::from(line)).collect()
+    }
+
+    #[test]
+    fn test_merge_lines() {
+        let base = to_strings(&["a", "b", "c", "d", "e"]);
+        let ours = to_strings(&["a", "B", "c", "d", "e"]);
+        let theirs = to_strings(&["a", "b", "c", "d", "E", "f"]);
+
+        let (merged, conflicted) = merge_lines(
+            &base,
+            &ours,
+            &theirs,
+            ("main", "feature"),
+            DiffAlgorithm::Default,
+        );
+
+        assert!(!conflicted);
+        assert_eq!(merged, to_strings(&["a", "B", "c", "d", "E", "f"]));
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let base = to_strings(&["a", "b", "c"]);
+        let ours = to_strings(&["a", "ours", "c"]);
+        let theirs = to_strings(&["a", "theirs", "c"]);
+
+        let (merged, conflicted) = merge_lines(
+            &base,
+            &ours,
+            &theirs,
+            ("main", "feature"),
+            DiffAlgorithm::Default,
+        );
+
+        assert!(conflicted);
+        assert_eq!(
+            merged,
+            to_strings(&[
+                "a",
+                "<<<<<<< main",
+                "ours",
+                "=======",
+                "theirs",
+                ">>>>>>> feature",
+                "c"
+            ])
+        );
+    }
+}
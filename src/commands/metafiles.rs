@@ -1,8 +1,10 @@
+use crate::commands::preview::DiffAlgorithm;
 use crate::errors::Error;
+use glob::Pattern;
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
 use std::time::SystemTime;
 
 /// Directory containing metadata
@@ -15,6 +17,22 @@ const KIFI_TRACKED: &str = "TRACKED.kifi";
 const KIFI_SNAPS: &str = "SNAPSHOTS.kifi";
 /// File containing paths of all files in the repo's root directory, tracked or otherwise
 const KIFI_FILECACHE: &str = "FILECACHE.kifi";
+/// File containing the name of the current branch
+const KIFI_HEAD: &str = "HEAD.kifi";
+/// File containing branches, and the snapshots they point to
+const KIFI_BRANCHES: &str = "BRANCHES.kifi";
+/// Branch created when a repository is initialised
+const DEFAULT_BRANCH: &str = "main";
+/// File containing tags, and the snapshots they point to
+const KIFI_TAGS: &str = "TAGS.kifi";
+/// File recording a merge that is waiting for conflicts to be resolved
+const KIFI_MERGE: &str = "MERGE.kifi";
+/// File used to edit snapshot messages when none was provided on the command line
+const KIFI_MESSAGE: &str = "MESSAGE.kifi";
+/// Directory containing the contents of snapshotted files, named by their hashes
+const KIFI_OBJECTS: &str = "objects";
+/// File containing settings for the repository
+const KIFI_CONFIG: &str = "CONFIG.kifi";
 
 /// The path to the root folder, and function to access files containing metadata
 pub struct Paths {
@@ -48,6 +66,27 @@ impl Paths {
     pub fn filecache(&self) -> PathBuf {
         self.kifi().join(KIFI_FILECACHE)
     }
+    pub fn objects(&self) -> PathBuf {
+        self.kifi().join(KIFI_OBJECTS)
+    }
+    pub fn head(&self) -> PathBuf {
+        self.kifi().join(KIFI_HEAD)
+    }
+    pub fn branches(&self) -> PathBuf {
+        self.kifi().join(KIFI_BRANCHES)
+    }
+    pub fn tags(&self) -> PathBuf {
+        self.kifi().join(KIFI_TAGS)
+    }
+    pub fn merge(&self) -> PathBuf {
+        self.kifi().join(KIFI_MERGE)
+    }
+    pub fn message(&self) -> PathBuf {
+        self.kifi().join(KIFI_MESSAGE)
+    }
+    pub fn config(&self) -> PathBuf {
+        self.kifi().join(KIFI_CONFIG)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -93,15 +132,27 @@ struct RepoFile {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileCache {
     files: HashMap<PathBuf, RepoFile>,
+    /// Ignored directories that weren't searched, so none of their files are listed
+    #[serde(default)]
+    ignored_directories: Vec<PathBuf>,
 }
 
 impl FileCache {
     pub fn new() -> Self {
         FileCache {
             files: HashMap::new(),
+            ignored_directories: Vec::new(),
         }
     }
 
+    pub fn add_ignored_directory(&mut self, directory: PathBuf) {
+        self.ignored_directories.push(directory);
+    }
+
+    pub fn get_ignored_directories(&self) -> &[PathBuf] {
+        &self.ignored_directories
+    }
+
     pub fn add_file(&mut self, file_path: PathBuf, status: FileStatus) {
         self.files.entry(file_path).or_insert(RepoFile { status });
     }
@@ -151,12 +202,67 @@ impl FileCache {
         }
     }
 
+    /// Finds files matching a path, a directory containing them, or a glob pattern
+    /// Results are sorted, and empty if nothing matched.
+    pub fn expand(&self, pathspec: &str) -> Vec<&PathBuf> {
+        // Leading `./` is dropped, so that `.` matches every file.
+        let prefix: PathBuf = Path::new(pathspec)
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .collect();
+
+        let mut files: Vec<&PathBuf> = if self.files.contains_key(&prefix) {
+            vec![
+                self.files
+                    .get_key_value(&prefix)
+                    .expect("The key was just checked to be present.")
+                    .0,
+            ]
+        } else {
+            let pattern = Pattern::new(pathspec).ok();
+            self.files
+                .keys()
+                .filter(|file| {
+                    file.starts_with(&prefix)
+                        || pattern.as_ref().is_some_and(|p| p.matches_path(file))
+                })
+                .collect()
+        };
+
+        files.sort();
+        files
+    }
+
+    /// Removes a file from the cache, returning its status
+    pub fn remove_file(&mut self, file_path: &PathBuf) -> Result<FileStatus, Error> {
+        self.files
+            .remove(file_path)
+            .map(|repo_file| repo_file.status)
+            .ok_or_else(|| Error::FileNotFoundInCache(file_path.clone()))
+    }
+
+    /// Moves a file's entry to a new path, keeping its status
+    pub fn rename_file(&mut self, from: &PathBuf, to: &Path) -> Result<(), Error> {
+        let status = self.remove_file(from)?;
+        self.files.insert(to.to_owned(), RepoFile { status });
+        Ok(())
+    }
+
     pub fn get_tracked_files(&self) -> Vec<&PathBuf> {
         let mut files = self.get_keys();
         files.retain(|&k| self.has_tracked_file(k));
         files
     }
 
+    /// Lists every directory containing a tracked file, however deeply
+    pub fn tracked_directories(&self) -> HashSet<&Path> {
+        self.get_tracked_files()
+            .into_iter()
+            .flat_map(|file| file.ancestors().skip(1))
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .collect()
+    }
+
     pub fn has_tracked_file(&self, file_path: &PathBuf) -> bool {
         match self.files.get(file_path) {
             Some(tracked) => match tracked.status {
@@ -187,24 +293,131 @@ impl Snapshots {
         Snapshots { list: Vec::new() }
     }
 
-    pub fn new_snap(&mut self, name: &String, user: &User) {
-        let snap = Snapshot::new(name, user);
+    pub fn new_snap(
+        &mut self,
+        name: &String,
+        user: &User,
+        tree: String,
+        message: String,
+        parents: Vec<String>,
+    ) {
+        let snap = Snapshot::new(name, user, tree, message, parents);
         self.list.insert(0, snap);
     }
 
-    pub fn get_last(&self) -> Result<&Snapshot, Error> {
-        self.list
-            .get(0)
-            .ok_or_else(|| Error::PreviewWithoutSnapshots)
+    pub fn get(&self, name: &str) -> Option<&Snapshot> {
+        self.list.iter().find(|s| s.name == name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Returns the most recently taken snapshot
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.list.iter().max_by_key(|s| s.created)
+    }
+
+    /// Fills in snapshots saved by older versions of kifi, which had no tree or parents
+    /// `store_tree` returns the hash of the tree holding a snapshot's files. Those versions kept a single
+    /// line of history, so each snapshot's parent is the one taken before it. Returns whether anything changed.
+    pub fn upgrade(
+        &mut self,
+        mut store_tree: impl FnMut(&Snapshot) -> Result<String, Error>,
+    ) -> Result<bool, Error> {
+        self.list.sort_by_key(|s| std::cmp::Reverse(s.created));
+
+        let mut changed = false;
+        for index in 0..self.list.len() {
+            if !self.list[index].tree.is_empty() {
+                continue;
+            }
+
+            let tree = store_tree(&self.list[index])?;
+            let parent = self.list.get(index + 1).map(|s| s.name.to_owned());
+            let snap = &mut self.list[index];
+            snap.tree = tree;
+            if snap.parents.is_empty() {
+                snap.parents.extend(parent);
+            }
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Returns the snapshot HEAD points to, `head` is `None` when no snapshots have been taken on the current branch
+    pub fn get_head(&self, head: Option<&String>) -> Result<&Snapshot, Error> {
+        let name = head.ok_or(Error::PreviewWithoutSnapshots)?;
+        self.get(name)
+            .ok_or_else(|| Error::SnapshotNotFound(name.to_owned()))
+    }
+
+    /// Returns the most recent snapshot that both `a` and `b` descend from
+    pub fn merge_base(&self, a: &str, b: &str) -> Option<&Snapshot> {
+        let ancestors: HashSet<&str> = self
+            .ancestry(a)
+            .into_iter()
+            .map(|s| s.name.as_str())
+            .collect();
+
+        // Ancestry lists descendants before their ancestors, so the first common snapshot is the most recent.
+        self.ancestry(b)
+            .into_iter()
+            .find(|s| ancestors.contains(s.name.as_str()))
+    }
+
+    /// Returns `from` and all of its ancestors, ordered so that every snapshot comes before its parents.
+    /// Parents are followed in order, so the first parent's line of history is listed first.
+    pub fn ancestry(&self, from: &str) -> Vec<&Snapshot> {
+        // Find every reachable snapshot, and how many of its children are reachable.
+        let mut children: HashMap<&str, usize> = HashMap::new();
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut stack: Vec<&str> = vec![from];
+
+        while let Some(name) = stack.pop() {
+            if !seen.insert(name) {
+                continue;
+            }
+            if let Some(snap) = self.get(name) {
+                for parent in &snap.parents {
+                    *children.entry(parent.as_str()).or_insert(0) += 1;
+                    stack.push(parent);
+                }
+            }
+        }
+
+        // A snapshot is listed once all of its reachable children have been listed.
+        let mut ancestry: Vec<&Snapshot> = Vec::new();
+        let mut ready: Vec<&str> = vec![from];
+
+        while let Some(name) = ready.pop() {
+            let snap = match self.get(name) {
+                Some(snap) => snap,
+                None => continue,
+            };
+            ancestry.push(snap);
+
+            for parent in snap.parents.iter().rev() {
+                let remaining = children
+                    .get_mut(parent.as_str())
+                    .expect("Every parent was counted while walking the ancestry.");
+                *remaining -= 1;
+                if *remaining == 0 {
+                    ready.push(parent);
+                }
+            }
+        }
+
+        ancestry
     }
 
-    pub fn find(self, name: String) -> SearchResults {
+    pub fn find(&self, name: String) -> SearchResults {
         let mut matches: Vec<Snapshot> = Vec::new();
 
         for s in &self.list {
             if s.name == name {
                 return SearchResults::FoundExact(s.clone());
-            } else if s.name.contains(&name) {
+            } else if s.name.contains(&name) || s.message.contains(&name) {
                 matches.push(s.clone())
             }
         }
@@ -217,16 +430,6 @@ impl Snapshots {
     }
 }
 
-impl IntoIterator for Snapshots {
-    type Item = Snapshot;
-    type IntoIter = std::vec::IntoIter<Snapshot>;
-
-    fn into_iter(mut self) -> Self::IntoIter {
-        self.list.sort_by(|a, b| a.created.cmp(&b.created));
-        self.list.into_iter()
-    }
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 /// Stores data about individual snapshots
 pub struct Snapshot {
@@ -234,17 +437,148 @@ pub struct Snapshot {
     pub author: String,
     pub author_email: String,
     pub created: SystemTime,
+    /// Hash of the snapshot's `Tree` in the object store
+    /// Snapshots from older versions of kifi have none until the repository is upgraded.
+    #[serde(default)]
+    pub tree: String,
+    #[serde(default)]
+    pub message: String,
+    /// Names of the snapshots this one descends from
+    #[serde(default)]
+    pub parents: Vec<String>,
 }
 
 impl Snapshot {
-    fn new(name: &String, user: &User) -> Snapshot {
+    fn new(
+        name: &String,
+        user: &User,
+        tree: String,
+        message: String,
+        parents: Vec<String>,
+    ) -> Snapshot {
         Snapshot {
             name: name.to_owned(),
             author: user.name().to_owned(),
             author_email: user.email().to_owned(),
             created: { SystemTime::now() },
+            tree,
+            message,
+            parents,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+/// Points at the current branch, new snapshots descend from the snapshot it points to
+pub struct Head {
+    branch: String,
+}
+
+impl Head {
+    pub fn new() -> Self {
+        Head {
+            branch: DEFAULT_BRANCH.to_string(),
         }
     }
+
+    pub fn branch(&self) -> &String {
+        &self.branch
+    }
+
+    pub fn switch(&mut self, branch: &str) {
+        self.branch = branch.to_owned();
+    }
+}
+
+impl Default for Head {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+/// Maps branch names to the names of the snapshots they point to
+pub struct Branches {
+    refs: BTreeMap<String, String>,
+}
+
+impl Branches {
+    pub fn new() -> Self {
+        Branches {
+            refs: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, branch: &str) -> Option<&String> {
+        self.refs.get(branch)
+    }
+
+    pub fn contains(&self, branch: &str) -> bool {
+        self.refs.contains_key(branch)
+    }
+
+    /// Points a branch at a snapshot, creating the branch if it doesn't exist
+    pub fn set(&mut self, branch: &str, snapshot: &str) {
+        self.refs.insert(branch.to_owned(), snapshot.to_owned());
+    }
+
+    pub fn create(&mut self, branch: &str, snapshot: &str) -> Result<(), Error> {
+        if self.contains(branch) {
+            return Err(Error::BranchExists(branch.to_owned()));
+        }
+
+        self.set(branch, snapshot);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, branch: &str) -> Result<String, Error> {
+        self.refs
+            .remove(branch)
+            .ok_or_else(|| Error::BranchNotFound(branch.to_owned()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.refs.iter()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+/// Maps paths of snapshotted files, relative to the root, to the hashes of their contents
+pub struct Tree {
+    files: BTreeMap<PathBuf, String>,
+}
+
+impl Tree {
+    pub fn new() -> Self {
+        Tree {
+            files: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, file_path: PathBuf, hash: String) {
+        self.files.insert(file_path, hash);
+    }
+
+    pub fn get(&self, file_path: &PathBuf) -> Option<&String> {
+        self.files.get(file_path)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (&PathBuf, &String)> {
+        self.files.iter()
+    }
+
+    /// Files that are at, or inside, the given path
+    pub fn files_under(&self, file_path: &Path) -> Vec<(&PathBuf, &String)> {
+        let prefix: PathBuf = file_path
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .collect();
+
+        self.files
+            .iter()
+            .filter(|(file, _)| file.starts_with(&prefix))
+            .collect()
+    }
 }
 
 /// Stores information about the user
@@ -281,7 +615,243 @@ impl User {
         &self.email
     }
 
-    pub fn kignore(&self) -> &Option<PathBuf> {
-        &self.kignore
+    /// Returns the global ignore file the user chose, `None` means the default one in kifi's config directory
+    pub fn global_kignore(&self) -> Option<PathBuf> {
+        self.kignore.to_owned()
+    }
+
+    /// Chooses the global ignore file, relative paths are taken from the current directory
+    /// `None` returns to the default one.
+    pub fn set_kignore(&mut self, kignore: Option<&Path>) -> Result<(), Error> {
+        self.kignore = match kignore {
+            Some(kignore) => Some(
+                std::path::absolute(kignore)
+                    .map_err(|e| Error::Canonicalize(e, kignore.to_owned()))?,
+            ),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Names of the user's settings, as given to `kifi config --global`
+    pub const KEYS: [&'static str; 1] = ["user.kignore"];
+
+    /// Returns a setting, `None` if it wasn't changed from its default
+    pub fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        match key {
+            "user.kignore" => Ok(self
+                .global_kignore()
+                .map(|kignore| kignore.display().to_string())),
+            _ => Err(Error::UnknownConfigKey(key.to_string())),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "user.kignore" => self.set_kignore(Some(Path::new(value))),
+            _ => Err(Error::UnknownConfigKey(key.to_string())),
+        }
+    }
+
+    /// Returns a setting to its default value
+    pub fn unset(&mut self, key: &str) -> Result<(), Error> {
+        match key {
+            "user.kignore" => self.set_kignore(None),
+            _ => Err(Error::UnknownConfigKey(key.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A name for a snapshot, annotated tags also record who created them and why
+pub struct Tag {
+    pub snapshot: String,
+    pub annotation: Option<Annotation>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Annotation {
+    pub tagger: String,
+    pub tagger_email: String,
+    pub created: SystemTime,
+    pub message: String,
+}
+
+impl Tag {
+    pub fn new(snapshot: &str, annotation: Option<(&User, String)>) -> Self {
+        Tag {
+            snapshot: snapshot.to_owned(),
+            annotation: annotation.map(|(user, message)| Annotation {
+                tagger: user.name().to_owned(),
+                tagger_email: user.email().to_owned(),
+                created: SystemTime::now(),
+                message,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+/// Maps tag names to tags
+pub struct Tags {
+    tags: BTreeMap<String, Tag>,
+}
+
+impl Tags {
+    pub fn new() -> Self {
+        Tags {
+            tags: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        self.tags.get(name)
+    }
+
+    pub fn create(&mut self, name: &str, tag: Tag) -> Result<(), Error> {
+        if self.tags.contains_key(name) {
+            return Err(Error::TagExists(name.to_owned()));
+        }
+
+        self.tags.insert(name.to_owned(), tag);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<Tag, Error> {
+        self.tags
+            .remove(name)
+            .ok_or_else(|| Error::TagNotFound(name.to_owned()))
+    }
+
+    /// Names of tags pointing at the given snapshot
+    pub fn names_for(&self, snapshot: &str) -> Vec<&String> {
+        self.tags
+            .iter()
+            .filter(|(_, tag)| tag.snapshot == snapshot)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Tag)> {
+        self.tags.iter()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+/// A merge in progress, the next snapshot records `theirs` as its second parent
+pub struct MergeState {
+    pub theirs: String,
+    pub message: String,
+    /// Files that were written with conflict markers
+    pub conflicts: Vec<PathBuf>,
+    /// Binary files changed on both sides, which stop the merge from finishing until they are
+    /// tracked again to keep them as they are, or removed
+    #[serde(default)]
+    pub binary_conflicts: Vec<PathBuf>,
+}
+
+impl MergeState {
+    /// Marks binary conflicts as resolved, returning whether any of the files were conflicts
+    pub fn resolve(&mut self, files: &[PathBuf]) -> bool {
+        let conflicts = self.binary_conflicts.len();
+        self.binary_conflicts.retain(|file| !files.contains(file));
+        self.binary_conflicts.len() != conflicts
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+/// Settings for a repository, changed with `kifi config`
+/// Settings missing from the file keep their defaults, so new ones can be added without breaking older repositories.
+pub struct Config {
+    pub diff_algorithm: DiffAlgorithm,
+}
+
+impl Config {
+    /// Names of the settings, as given to `kifi config`
+    pub const KEYS: [&'static str; 1] = ["diff.algorithm"];
+
+    pub fn get(&self, key: &str) -> Result<String, Error> {
+        match key {
+            "diff.algorithm" => Ok(self.diff_algorithm.to_string()),
+            _ => Err(Error::UnknownConfigKey(key.to_string())),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        match key {
+            "diff.algorithm" => self.diff_algorithm = value.parse()?,
+            _ => return Err(Error::UnknownConfigKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Returns a setting to its default value
+    pub fn unset(&mut self, key: &str) -> Result<(), Error> {
+        match key {
+            "diff.algorithm" => self.diff_algorithm = DiffAlgorithm::default(),
+            _ => return Err(Error::UnknownConfigKey(key.to_string())),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Snapshots, User};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_user_kignore() {
+        let mut user = User::new(&String::from("test"), &String::from("test@testing.com"))
+            .expect("The email is valid.");
+        assert_eq!(None, user.get("user.kignore").expect("The key is valid."));
+
+        user.set("user.kignore", "global.kignore")
+            .expect("The path can be made absolute.");
+        let chosen = user
+            .get("user.kignore")
+            .expect("The key is valid.")
+            .map(PathBuf::from)
+            .expect("The setting was changed.");
+        assert!(chosen.is_absolute() && chosen.ends_with("global.kignore"));
+
+        user.unset("user.kignore").expect("The key is valid.");
+        assert_eq!(None, user.get("user.kignore").expect("The key is valid."));
+        assert!(user.get("user.name").is_err());
+        assert!(user.set("user.name", "someone").is_err());
+    }
+
+    #[test]
+    fn test_ancestry() {
+        let user = User::new(&String::from("test"), &String::from("test@testing.com"))
+            .expect("The email is valid.");
+        let mut snapshots = Snapshots::new();
+
+        // a <- b <- d, a <- c <- d, with d merging b and c
+        let snaps = [
+            ("a", vec![]),
+            ("b", vec!["a"]),
+            ("c", vec!["a"]),
+            ("d", vec!["b", "c"]),
+            ("e", vec!["a"]),
+        ];
+        for (name, parents) in snaps {
+            snapshots.new_snap(
+                &String::from(name),
+                &user,
+                String::new(),
+                String::new(),
+                parents.into_iter().map(String::from).collect(),
+            );
+        }
+
+        let names: Vec<&str> = snapshots
+            .ancestry("d")
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+
+        assert_eq!(names, vec!["d", "b", "c", "a"]);
     }
 }
@@ -0,0 +1,120 @@
+use crate::commands::metafiles::{Paths, Tree};
+use crate::errors::Error;
+use serde_cbor::{from_reader, to_vec};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Hashes the given contents, the result is used as the object's name in the store
+pub fn hash_object(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Path to an object in the store, the first two characters of the hash are used as a subdirectory
+pub fn object_path(path: &Paths, hash: &str) -> PathBuf {
+    let (prefix, rest) = hash.split_at(2);
+    path.objects().join(prefix).join(rest)
+}
+
+/// Writes contents to the store and returns their hash
+/// Contents that are already present in the store are not written again.
+pub fn write_object(path: &Paths, contents: &[u8]) -> Result<String, Error> {
+    let hash = hash_object(contents);
+    let object = object_path(path, &hash);
+
+    if !object.is_file() {
+        let parent = object
+            .parent()
+            .expect("Objects are always stored inside a subdirectory of the store.");
+        fs::create_dir_all(parent).map_err(Error::CreateDirectory)?;
+
+        // Writing to a temporary file first ensures an interrupted write never leaves a truncated object behind.
+        let temporary = parent.join(format!("{}.tmp", &hash[2..]));
+        fs::write(&temporary, contents).map_err(Error::CreateFile)?;
+        fs::rename(&temporary, &object).map_err(Error::CreateFile)?;
+    }
+
+    Ok(hash)
+}
+
+/// Reads an object from the store
+pub fn read_object(path: &Paths, hash: &str) -> Result<Vec<u8>, Error> {
+    let object = object_path(path, hash);
+    if !object.is_file() {
+        return Err(Error::ObjectNotFound(hash.to_owned()));
+    }
+
+    fs::read(object).map_err(Error::ReadFile)
+}
+
+/// Stores a file from the working tree, `file` is relative to the repository root
+pub fn store_file(path: &Paths, file: &Path) -> Result<String, Error> {
+    let contents = fs::read(path.root().join(file)).map_err(Error::ReadFile)?;
+    write_object(path, &contents)
+}
+
+/// Copies an object out of the store to `destination`, creating parent directories as needed
+pub fn restore_file(path: &Paths, hash: &str, destination: &Path) -> Result<(), Error> {
+    let object = object_path(path, hash);
+    if !object.is_file() {
+        return Err(Error::ObjectNotFound(hash.to_owned()));
+    }
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(Error::CreateDirectory)?;
+    }
+
+    fs::copy(&object, destination)
+        .map_err(|e| Error::FileCopy(object, destination.to_owned(), e))?;
+
+    Ok(())
+}
+
+/// Restores every file in a tree to the working tree
+pub fn restore_tree(path: &Paths, tree: &Tree) -> Result<(), Error> {
+    for (file, hash) in tree.files() {
+        restore_file(path, hash, &path.root().join(file))?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the files of `current` in the working tree with those of `target`
+/// Files that only exist in `current` are removed.
+pub fn checkout_tree(path: &Paths, current: Option<&Tree>, target: &Tree) -> Result<(), Error> {
+    if let Some(current) = current {
+        for (file, _) in current.files() {
+            if target.get(file).is_none() {
+                remove_file(path, file)?;
+            }
+        }
+    }
+
+    restore_tree(path, target)
+}
+
+/// Removes a file from the working tree, `file` is relative to the repository root
+/// Files that are already missing are ignored.
+pub fn remove_file(path: &Paths, file: &Path) -> Result<(), Error> {
+    match fs::remove_file(path.root().join(file)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::RemoveFile(e)),
+    }
+}
+
+/// Stores a tree and returns its hash
+pub fn write_tree(path: &Paths, tree: &Tree) -> Result<String, Error> {
+    let contents = to_vec(tree).map_err(Error::CBORWriter)?;
+    write_object(path, &contents)
+}
+
+/// Reads a tree from the store
+pub fn read_tree(path: &Paths, hash: &str) -> Result<Tree, Error> {
+    let contents = read_object(path, hash)?;
+    from_reader(&contents[..]).map_err(Error::CBORReader)
+}
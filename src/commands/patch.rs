@@ -0,0 +1,552 @@
+use crate::commands::common::format_time;
+use crate::commands::metafiles::Snapshot;
+use crate::commands::preview::{from_lines, split_newline, to_lines, NO_NEWLINE};
+use crate::errors::Error;
+use crate::output::Output;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Most lines of context that may be ignored at either end of a hunk, when it doesn't match exactly
+const MAX_FUZZ: usize = 2;
+
+/// Longest a patch file's name can be, not counting its number and extension
+const MAX_NAME_LENGTH: usize = 52;
+
+/// Lines at the start of a patch file, describing the snapshot it was made from
+/// `number` and `total` give the patch's place in the series, as in `[PATCH 1/3]`.
+pub fn patch_header(
+    snapshot: &Snapshot,
+    number: usize,
+    total: usize,
+) -> Result<Vec<String>, Error> {
+    let mut message = snapshot.message.lines();
+    let subject = message.next().unwrap_or_default();
+
+    let mut header = vec![
+        format!("From {}", snapshot.name),
+        format!("From: {} <{}>", snapshot.author, snapshot.author_email),
+        format!("Date: {} UTC", format_time(snapshot.created)?),
+        format!("Subject: [PATCH {}/{}] {}", number, total, subject),
+        String::new(),
+    ];
+
+    // The rest of the message follows the subject, without the blank line separating them.
+    let body: Vec<&str> = message.skip_while(|line| line.trim().is_empty()).collect();
+    if !body.is_empty() {
+        header.extend(body.into_iter().map(String::from));
+        header.push(String::new());
+    }
+    header.push(String::from("---"));
+
+    Ok(header)
+}
+
+/// Names a patch file after its place in the series and its subject, such as `0001-fix-typo.patch`
+pub fn patch_file_name(number: usize, subject: &str) -> String {
+    let mut name = String::new();
+    for c in subject.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        } else if !name.is_empty() && !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    name.truncate(MAX_NAME_LENGTH);
+    let name = name.trim_end_matches('-');
+
+    match name.is_empty() {
+        true => format!("{:04}.patch", number),
+        false => format!("{:04}-{}.patch", number, name),
+    }
+}
+
+/// A patch read from a file, as written by `kifi format-patch` or any unified diff
+pub struct Patch {
+    pub subject: Option<String>,
+    pub files: Vec<FilePatch>,
+}
+
+/// Changes to a single file
+pub struct FilePatch {
+    /// The file before the changes, `None` if the patch creates it
+    pub old: Option<PathBuf>,
+    /// The file after the changes, `None` if the patch deletes it
+    pub new: Option<PathBuf>,
+    /// Binary changes only record that the file differs, so they can't be applied
+    pub binary: bool,
+    /// Lines naming the file in the patch, written at the start of a `.rej` file
+    header: Vec<String>,
+    hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// The file's path in the working tree, after the changes unless it is being deleted
+    pub fn path(&self) -> &PathBuf {
+        self.new
+            .as_ref()
+            .or(self.old.as_ref())
+            .expect("Patches for files that are neither old nor new are skipped while parsing.")
+    }
+}
+
+/// A hunk, with the lines it expects to find and the lines it replaces them with
+struct Hunk {
+    /// Line in the old file where the hunk starts, counting from 1
+    old_start: usize,
+    /// Context and removed lines
+    old: Vec<String>,
+    /// Context and inserted lines
+    new: Vec<String>,
+    /// Lines of context before the first change
+    leading: usize,
+    /// Lines of context after the last change
+    trailing: usize,
+    /// The hunk as it appears in the patch, written to a `.rej` file if it can't be applied
+    text: Vec<String>,
+}
+
+/// Reads a patch, skipping anything that isn't part of a file's changes
+pub fn parse_patch(contents: &[u8]) -> Result<Patch, Error> {
+    let lines: Vec<String> = to_lines(contents)
+        .iter()
+        .map(|line| split_newline(line).0.to_string())
+        .collect();
+
+    let mut patch = Patch {
+        subject: None,
+        files: Vec::new(),
+    };
+    let mut index = 0;
+
+    // Patches from `kifi format-patch` start with the snapshot's details, up to a blank line.
+    if lines.first().is_some_and(|line| line.starts_with("From ")) {
+        while index < lines.len() && !lines[index].is_empty() {
+            if let Some(subject) = lines[index].strip_prefix("Subject: ") {
+                // `[PATCH 1/3]` is only needed while the patches are kept together.
+                let subject = match subject.starts_with('[') {
+                    true => subject
+                        .split_once("] ")
+                        .map_or(subject, |(_, subject)| subject),
+                    false => subject,
+                };
+                patch.subject = Some(subject.to_string());
+            }
+            index += 1;
+        }
+    }
+
+    while index < lines.len() {
+        let line = &lines[index];
+
+        if let Some((old, new)) = line
+            .strip_prefix("Binary files ")
+            .and_then(|names| names.split_once(" and "))
+            .and_then(|(old, rest)| rest.split_once(" differ").map(|(new, _)| (old, new)))
+        {
+            let (old, new) = (file_name(old)?, file_name(new)?);
+            if old.is_some() || new.is_some() {
+                patch.files.push(FilePatch {
+                    old,
+                    new,
+                    binary: true,
+                    header: vec![line.to_owned()],
+                    hunks: Vec::new(),
+                });
+            }
+            index += 1;
+            continue;
+        }
+
+        let next = lines.get(index + 1);
+        match (
+            line.strip_prefix("--- "),
+            next.and_then(|l| l.strip_prefix("+++ ")),
+        ) {
+            (Some(old), Some(new)) => {
+                let header = vec![line.to_owned(), next.cloned().unwrap_or_default()];
+                let (old, new) = (file_name(old)?, file_name(new)?);
+                index += 2;
+
+                let mut hunks: Vec<Hunk> = Vec::new();
+                while lines.get(index).is_some_and(|line| line.starts_with("@@ ")) {
+                    let (hunk, next_index) = parse_hunk(&lines, index)?;
+                    hunks.push(hunk);
+                    index = next_index;
+                }
+
+                if old.is_some() || new.is_some() {
+                    patch.files.push(FilePatch {
+                        old,
+                        new,
+                        binary: false,
+                        header,
+                        hunks,
+                    });
+                }
+            }
+            _ => index += 1,
+        }
+    }
+
+    if patch.files.is_empty() {
+        return Err(Error::InvalidPatch(String::from(
+            "it doesn't change any files",
+        )));
+    }
+
+    Ok(patch)
+}
+
+/// Reads a file's name from a `---` or `+++` line, `None` means `/dev/null`
+/// Names must stay inside the repository, so absolute paths and `..` are rejected.
+fn file_name(name: &str) -> Result<Option<PathBuf>, Error> {
+    // Some tools add a timestamp after the name, separated by a tab.
+    let name = name.split('\t').next().unwrap_or(name);
+    if name == "/dev/null" {
+        return Ok(None);
+    }
+
+    let name = name
+        .strip_prefix("a/")
+        .or_else(|| name.strip_prefix("b/"))
+        .unwrap_or(name);
+    let path = PathBuf::from(name);
+    let outside = path.components().any(|component| {
+        matches!(
+            component,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_)
+        )
+    });
+    if outside || path.file_name().is_none() {
+        return Err(Error::InvalidPatch(format!(
+            "'{}' is not a path inside the repository",
+            name
+        )));
+    }
+
+    Ok(Some(path))
+}
+
+/// Reads a range from a hunk header, such as `12,3`, where a missing count means 1
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// Reads the hunk starting at `index`, returning it and the index of the line after it
+fn parse_hunk(lines: &[String], index: usize) -> Result<(Hunk, usize), Error> {
+    let header = &lines[index];
+    let invalid =
+        |line: usize| Error::InvalidPatch(format!("line {} is not a valid hunk", line + 1));
+
+    let mut ranges = header.split(' ').skip(1);
+    let ((old_start, old_count), (_, new_count)) = match (
+        ranges
+            .next()
+            .and_then(|r| r.strip_prefix('-'))
+            .and_then(parse_range),
+        ranges
+            .next()
+            .and_then(|r| r.strip_prefix('+'))
+            .and_then(parse_range),
+    ) {
+        (Some(old), Some(new)) => (old, new),
+        _ => return Err(invalid(index)),
+    };
+
+    let mut hunk = Hunk {
+        old_start,
+        old: Vec::new(),
+        new: Vec::new(),
+        leading: 0,
+        trailing: 0,
+        text: vec![header.to_owned()],
+    };
+    let mut changed = false;
+    let mut index = index + 1;
+
+    while index < lines.len()
+        && (hunk.old.len() < old_count
+            || hunk.new.len() < new_count
+            || lines[index].starts_with('\\'))
+    {
+        let line = &lines[index];
+        // Blank context lines sometimes lose their leading space when patches are emailed.
+        let mut chars = line.chars();
+        let prefix = chars.next().unwrap_or(' ');
+        let text = chars.as_str();
+
+        match prefix {
+            ' ' => {
+                hunk.old.push(text.to_string());
+                hunk.new.push(text.to_string());
+                match changed {
+                    false => hunk.leading += 1,
+                    true => hunk.trailing += 1,
+                }
+            }
+            '-' | '+' => {
+                match prefix {
+                    '-' => hunk.old.push(text.to_string()),
+                    _ => hunk.new.push(text.to_string()),
+                }
+                changed = true;
+                hunk.trailing = 0;
+            }
+            // The line before has no newline, on whichever side it belongs to.
+            '\\' => {
+                let previous = &lines[index - 1];
+                if !previous.starts_with('+') {
+                    if let Some(last) = hunk.old.last_mut() {
+                        last.push(NO_NEWLINE);
+                    }
+                }
+                if !previous.starts_with('-') {
+                    if let Some(last) = hunk.new.last_mut() {
+                        last.push(NO_NEWLINE);
+                    }
+                }
+            }
+            _ => return Err(invalid(index)),
+        }
+
+        hunk.text.push(line.to_owned());
+        index += 1;
+    }
+
+    if hunk.old.len() != old_count || hunk.new.len() != new_count {
+        return Err(invalid(index.min(lines.len() - 1)));
+    }
+
+    Ok((hunk, index))
+}
+
+/// Finds where `old` appears in `lines`, starting at `expected` and moving further away from it
+fn find_lines(lines: &[String], old: &[String], expected: usize) -> Option<usize> {
+    let last = lines.len().checked_sub(old.len())?;
+    let expected = expected.min(last);
+
+    (0..=last).find_map(|distance| {
+        [expected.checked_sub(distance), Some(expected + distance)]
+            .into_iter()
+            .flatten()
+            .find(|&position| position <= last && lines[position..position + old.len()] == *old)
+    })
+}
+
+/// Where a hunk was applied, and how far it had to move or how much context it ignored
+struct Applied {
+    line: usize,
+    offset: isize,
+    fuzz: usize,
+}
+
+/// Applies hunks to lines in order, returning where each one was applied, or `None` if it was rejected
+fn apply_hunks(lines: &mut Vec<String>, hunks: &[Hunk]) -> Vec<Option<Applied>> {
+    // Lines added by earlier hunks, and how far the last hunk had moved, shift where the next is expected.
+    let mut shift: isize = 0;
+    let mut last_offset: isize = 0;
+    let mut results: Vec<Option<Applied>> = Vec::new();
+
+    for hunk in hunks {
+        let mut result = None;
+
+        for fuzz in 0..=MAX_FUZZ {
+            let (start, end) = (fuzz.min(hunk.leading), fuzz.min(hunk.trailing));
+            let old = &hunk.old[start..hunk.old.len() - end];
+            let new = &hunk.new[start..hunk.new.len() - end];
+            // Without any lines to find, a hunk could be applied anywhere.
+            if old.is_empty() && !hunk.old.is_empty() {
+                break;
+            }
+
+            // A hunk that only inserts lines gives the line before them as its start.
+            let start_line = match hunk.old.is_empty() {
+                true => hunk.old_start,
+                false => hunk.old_start.saturating_sub(1),
+            };
+            let planned = (start_line + start) as isize + shift;
+            let expected = (planned + last_offset).max(0) as usize;
+
+            if let Some(position) = find_lines(lines, old, expected) {
+                lines.splice(position..position + old.len(), new.iter().cloned());
+                let offset = position as isize - planned;
+                shift += new.len() as isize - old.len() as isize;
+                last_offset = offset;
+                // Like the hunk header, the line reported includes any context that was ignored.
+                result = Some(Applied {
+                    line: position.saturating_sub(start) + 1,
+                    offset,
+                    fuzz,
+                });
+                break;
+            }
+        }
+
+        results.push(result);
+    }
+
+    results
+}
+
+/// Applies changes to a file in the working tree, reporting hunks that had to be moved or fuzzed
+/// Hunks that can't be applied are written to a `.rej` file next to it, whose path is returned.
+pub fn apply_file(
+    root: &Path,
+    file: &FilePatch,
+    output: &mut dyn Output,
+) -> Result<Option<PathBuf>, Error> {
+    let destination = root.join(file.path());
+    let rejects = PathBuf::from(format!("{}.rej", file.path().display()));
+    output.add(format!("patching file {}", file.path().display()));
+
+    let reject_all = |reason: &str, output: &mut dyn Output| -> Result<Option<PathBuf>, Error> {
+        output.add(format!(
+            "{}, saving all changes to {}",
+            reason,
+            rejects.display()
+        ));
+        let mut text = file.header.clone();
+        text.extend(file.hunks.iter().flat_map(|hunk| hunk.text.clone()));
+        fs::write(root.join(&rejects), from_lines(&text)).map_err(Error::CreateFile)?;
+        Ok(Some(rejects.clone()))
+    };
+
+    if file.binary {
+        return reject_all("Binary changes can't be applied", output);
+    }
+
+    let mut lines = match &file.old {
+        Some(old) => match fs::read(root.join(old)) {
+            Ok(contents) => to_lines(&contents),
+            Err(_) => return reject_all("The file doesn't exist", output),
+        },
+        None if destination.exists() => return reject_all("The file already exists", output),
+        None => Vec::new(),
+    };
+
+    let results = apply_hunks(&mut lines, &file.hunks);
+    let mut rejected: Vec<&Hunk> = Vec::new();
+
+    for (number, (hunk, result)) in file.hunks.iter().zip(results).enumerate() {
+        match result {
+            Some(Applied { line, offset, fuzz }) => {
+                let mut notes: Vec<String> = Vec::new();
+                if fuzz > 0 {
+                    notes.push(format!("with fuzz {}", fuzz));
+                }
+                if offset != 0 {
+                    let plural = if offset.abs() == 1 { "line" } else { "lines" };
+                    notes.push(format!("(offset {} {})", offset, plural));
+                }
+                if !notes.is_empty() {
+                    output.add(format!(
+                        "Hunk #{} succeeded at {} {}.",
+                        number + 1,
+                        line,
+                        notes.join(" ")
+                    ));
+                }
+            }
+            None => {
+                output.add(format!(
+                    "Hunk #{} FAILED at {}.",
+                    number + 1,
+                    hunk.old_start
+                ));
+                rejected.push(hunk);
+            }
+        }
+    }
+
+    // A deleted file is only removed once everything in it has been removed.
+    match &file.new {
+        None if lines.is_empty() => {
+            if let Some(old) = &file.old {
+                fs::remove_file(root.join(old)).map_err(Error::RemoveFile)?;
+            }
+        }
+        _ => {
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent).map_err(Error::CreateDirectory)?;
+            }
+            fs::write(&destination, from_lines(&lines)).map_err(Error::CreateFile)?;
+            if let (Some(old), Some(new)) = (&file.old, &file.new) {
+                if old != new {
+                    fs::remove_file(root.join(old)).map_err(Error::RemoveFile)?;
+                }
+            }
+        }
+    }
+
+    if rejected.is_empty() {
+        return Ok(None);
+    }
+
+    output.add(format!(
+        "{} out of {} hunks FAILED -- saving rejects to file {}",
+        rejected.len(),
+        file.hunks.len(),
+        rejects.display()
+    ));
+    let mut text = file.header.clone();
+    text.extend(rejected.iter().flat_map(|hunk| hunk.text.clone()));
+    fs::write(root.join(&rejects), from_lines(&text)).map_err(Error::CreateFile)?;
+
+    Ok(Some(rejects))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_hunks, parse_patch, patch_file_name};
+    use crate::errors::Error;
+
+    #[test]
+    fn test_apply_with_offset_and_fuzz() {
+        let patch =
+            parse_patch(b"--- a/list\n+++ b/list\n@@ -2,5 +2,5 @@\n b\n c\n-d\n+D\n e\n f\n")
+                .expect("the patch should be valid");
+        let hunks = &patch.files[0].hunks;
+
+        // Two lines were added above the hunk, and the line after the change is different.
+        let mut lines: Vec<String> = ["x", "y", "a", "b", "c", "d", "e", "F"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let results = apply_hunks(&mut lines, hunks);
+
+        let applied = results[0].as_ref().expect("the hunk should be applied");
+        assert_eq!((4, 2, 1), (applied.line, applied.offset, applied.fuzz));
+        assert_eq!(vec!["x", "y", "a", "b", "c", "D", "e", "F"], lines);
+
+        let mut lines: Vec<String> = ["a", "b", "c", "e"].into_iter().map(String::from).collect();
+        assert!(apply_hunks(&mut lines, hunks)[0].is_none());
+    }
+
+    #[test]
+    fn test_paths_outside_repository() {
+        for name in ["../outside", "a/../../outside", "/etc/passwd", "a/"] {
+            let patch = format!("--- {0}\n+++ {0}\n@@ -1 +1 @@\n-a\n+b\n", name);
+            assert!(
+                matches!(parse_patch(patch.as_bytes()), Err(Error::InvalidPatch(_))),
+                "'{}' should be rejected",
+                name
+            );
+        }
+
+        let patch = parse_patch(b"--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1 +1 @@\n-a\n+b\n")
+            .expect("paths inside the repository should be accepted");
+        assert_eq!(std::path::Path::new("src/main.rs"), patch.files[0].path());
+    }
+
+    #[test]
+    fn test_patch_file_name() {
+        assert_eq!(
+            "0001-fix-the-parser-s-handling-of-tabs.patch",
+            patch_file_name(1, "Fix the parser's handling of tabs!")
+        );
+        assert_eq!("0012.patch", patch_file_name(12, "..."));
+    }
+}
@@ -1,15 +1,24 @@
+use crate::commands::metafiles::{Paths, Tree};
+use crate::commands::objects::{hash_object, read_object};
 use crate::errors::Error;
 use crate::output::Output;
+use glob::Pattern;
+use serde_derive::{Deserialize, Serialize};
+use slice_diff_patch::Change;
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
 
 pub fn generate_diffs(
     snapped_file: Vec<String>,
     current_file: Vec<String>,
+    comparison: Comparison,
+    words: Option<WordDiff>,
     output: &mut dyn Output,
 ) -> Result<(), Error> {
-    let changes = slice_diff_patch::lcs_diff(&snapped_file, &current_file);
+    let changes = comparison.changes(&snapped_file, &current_file);
     if changes.is_empty() {
         return Ok(());
     }
@@ -18,26 +27,1163 @@ pub fn generate_diffs(
     #[cfg(debug_assertions)]
     println!("{:?}\n", &changes);
 
-    generate_output_from_diffs(snapped_file, changes, output)?;
+    generate_output_from_diffs(snapped_file, changes, comparison, words, output)?;
     Ok(())
 }
 
-pub fn read_lines(path: &PathBuf) -> Result<Vec<String>, Error> {
-    let file = fs::File::open(path).map_err(Error::ReadFile)?;
-    let reader = BufReader::new(file);
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+/// Algorithms that can be used to find the changes between two files
+pub enum DiffAlgorithm {
+    /// Finds the fewest lines to insert and delete, from the longest common subsequence of lines
+    #[default]
+    Default,
+    /// Myers' algorithm, in Wu's O(NP) form, which also finds the fewest lines to insert and delete
+    Myers,
+    /// Aligns lines that appear exactly once in both files first, so hunks follow the structure of the code
+    Patience,
+    /// Like patience, but aligns the least common lines first, even if they aren't unique
+    Histogram,
+}
+
+/// Names accepted for each algorithm, in the order they are listed in help messages
+pub const DIFF_ALGORITHMS: [&str; 4] = ["default", "myers", "patience", "histogram"];
+
+impl FromStr for DiffAlgorithm {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "default" => Ok(DiffAlgorithm::Default),
+            "myers" => Ok(DiffAlgorithm::Myers),
+            "patience" => Ok(DiffAlgorithm::Patience),
+            "histogram" => Ok(DiffAlgorithm::Histogram),
+            _ => Err(Error::UnknownDiffAlgorithm(name.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for DiffAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DiffAlgorithm::Default => DIFF_ALGORITHMS[0],
+            DiffAlgorithm::Myers => DIFF_ALGORITHMS[1],
+            DiffAlgorithm::Patience => DIFF_ALGORITHMS[2],
+            DiffAlgorithm::Histogram => DIFF_ALGORITHMS[3],
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl DiffAlgorithm {
+    /// Lists the changes that turn `old` into `new`
+    pub fn changes(&self, old: &[String], new: &[String]) -> Vec<Change<String>> {
+        let mut pairs: Vec<(usize, usize)> = Vec::new();
+        match self {
+            // The default keeps the diff kifi has always used.
+            DiffAlgorithm::Default => return slice_diff_patch::lcs_diff(old, new),
+            DiffAlgorithm::Myers => myers(old, new, (0, 0), &mut pairs),
+            DiffAlgorithm::Patience => patience(old, new, (0, 0), &mut pairs),
+            DiffAlgorithm::Histogram => histogram(old, new, (0, 0), &mut pairs),
+        }
+
+        changes_from_pairs(old, new, &pairs)
+    }
+
+    /// Matches lines of `old` to unchanged lines of `new`, using the same diff as `generate_diffs`
+    /// The result holds, for every line in `old`, the index of the same line in `new` if it wasn't changed.
+    pub fn line_matches(&self, old: &[String], new: &[String]) -> Vec<Option<usize>> {
+        matches_from_changes(old.len(), self.changes(old, new))
+    }
+}
+
+/// Replays changes made to a file of `old_len` lines, to find where each of its lines ended up
+fn matches_from_changes(old_len: usize, changes: Vec<Change<String>>) -> Vec<Option<usize>> {
+    // This tracks which line of the old file each line came from.
+    let mut origins: Vec<Option<usize>> = (0..old_len).map(Some).collect();
+
+    for change in changes {
+        match change {
+            Change::Remove(index) => {
+                origins.remove(index);
+            }
+            Change::Insert((index, _)) => {
+                origins.insert(index, None);
+            }
+            Change::Update((index, _)) => {
+                origins[index] = None;
+            }
+        }
+    }
+
+    let mut matches: Vec<Option<usize>> = vec![None; old_len];
+    for (new_index, origin) in origins.into_iter().enumerate() {
+        if let Some(old_index) = origin {
+            matches[old_index] = Some(new_index);
+        }
+    }
+
+    matches
+}
+
+/// Turns pairs of matching lines, in order, into the changes needed for the lines between them
+fn changes_from_pairs(
+    old: &[String],
+    new: &[String],
+    pairs: &[(usize, usize)],
+) -> Vec<Change<String>> {
+    let mut changes: Vec<Change<String>> = Vec::new();
+    // `position` is where the next line goes in the file as it is being changed.
+    let (mut old_index, mut new_index, mut position) = (0, 0, 0);
+
+    let end = (old.len(), new.len());
+    for &(old_match, new_match) in pairs.iter().chain(std::iter::once(&end)) {
+        for _ in old_index..old_match {
+            slice_diff_patch::remove(position, &mut changes);
+        }
+        for line in &new[new_index..new_match] {
+            slice_diff_patch::insert(position, line, &mut changes);
+            position += 1;
+        }
+        (old_index, new_index) = (old_match + 1, new_match + 1);
+        position += 1;
+    }
+
+    changes
+}
+
+/// Matches the lines both ends of `old` and `new` have in common, returning how many match at the start and end
+/// `offset` is where `old` and `new` start in the files being compared.
+fn match_ends(
+    old: &[String],
+    new: &[String],
+    offset: (usize, usize),
+    pairs: &mut Vec<(usize, usize)>,
+) -> (usize, usize) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    pairs.extend((0..prefix).map(|i| (offset.0 + i, offset.1 + i)));
+    (prefix, suffix)
+}
+
+/// Adds the pairs found by Myers' algorithm, which the other algorithms also fall back to when they find nothing to align on
+fn myers(old: &[String], new: &[String], offset: (usize, usize), pairs: &mut Vec<(usize, usize)>) {
+    let matches = matches_from_changes(old.len(), slice_diff_patch::wu_diff(old, new));
+    pairs.extend(
+        matches
+            .into_iter()
+            .enumerate()
+            .filter_map(|(o, n)| n.map(|n| (offset.0 + o, offset.1 + n))),
+    );
+}
+
+/// Adds the suffix matched by `match_ends`, which comes after everything else in the range
+fn push_suffix(
+    old: &[String],
+    new: &[String],
+    offset: (usize, usize),
+    suffix: usize,
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    pairs.extend((0..suffix).map(|i| {
+        (
+            offset.0 + old.len() - suffix + i,
+            offset.1 + new.len() - suffix + i,
+        )
+    }));
+}
 
-    let mut lines: Vec<String> = Vec::new();
+/// Patience diff, pairs are added in order
+fn patience(
+    old: &[String],
+    new: &[String],
+    offset: (usize, usize),
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    let (prefix, suffix) = match_ends(old, new, offset, pairs);
+    let inner_old = &old[prefix..old.len() - suffix];
+    let inner_new = &new[prefix..new.len() - suffix];
+    let inner_offset = (offset.0 + prefix, offset.1 + prefix);
 
-    for line in reader.lines() {
-        lines.push(line.map_err(Error::ReadFile)?);
+    if !inner_old.is_empty() && !inner_new.is_empty() {
+        // Lines that appear exactly once on each side, ordered by where they are in `old`.
+        let mut counts: HashMap<&String, (usize, usize, usize)> = HashMap::new();
+        for (index, line) in inner_old.iter().enumerate() {
+            let entry = counts.entry(line).or_insert((0, 0, 0));
+            entry.0 += 1;
+            entry.2 = index;
+        }
+        for line in inner_new {
+            if let Some(entry) = counts.get_mut(line) {
+                entry.1 += 1;
+            }
+        }
+        let unique: Vec<(usize, usize)> = inner_new
+            .iter()
+            .enumerate()
+            .filter_map(|(new_index, line)| match counts.get(line) {
+                Some(&(1, 1, old_index)) => Some((old_index, new_index)),
+                _ => None,
+            })
+            .collect();
+
+        let anchors = longest_increasing(unique);
+        if anchors.is_empty() {
+            myers(inner_old, inner_new, inner_offset, pairs);
+        } else {
+            let (mut old_start, mut new_start) = (0, 0);
+            for (old_anchor, new_anchor) in anchors {
+                patience(
+                    &inner_old[old_start..old_anchor],
+                    &inner_new[new_start..new_anchor],
+                    (inner_offset.0 + old_start, inner_offset.1 + new_start),
+                    pairs,
+                );
+                pairs.push((inner_offset.0 + old_anchor, inner_offset.1 + new_anchor));
+                (old_start, new_start) = (old_anchor + 1, new_anchor + 1);
+            }
+            patience(
+                &inner_old[old_start..],
+                &inner_new[new_start..],
+                (inner_offset.0 + old_start, inner_offset.1 + new_start),
+                pairs,
+            );
+        }
     }
 
-    Ok(lines)
+    push_suffix(old, new, offset, suffix, pairs);
+}
+
+/// Finds the longest run of pairs, taken in the given order, whose first values are increasing
+/// The pairs are ordered by their second values, so the result is increasing in both.
+fn longest_increasing(pairs: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    // `tails[n]` is the pair ending the best run of length `n + 1` found so far.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; pairs.len()];
+
+    for (index, &(old_index, _)) in pairs.iter().enumerate() {
+        let length = tails.partition_point(|&tail| pairs[tail].0 < old_index);
+        if length > 0 {
+            previous[index] = Some(tails[length - 1]);
+        }
+        match tails.get_mut(length) {
+            Some(tail) => *tail = index,
+            None => tails.push(index),
+        }
+    }
+
+    let mut run: Vec<(usize, usize)> = Vec::new();
+    let mut current = tails.last().copied();
+    while let Some(index) = current {
+        run.push(pairs[index]);
+        current = previous[index];
+    }
+    run.reverse();
+    run
+}
+
+/// Histogram diff, pairs are added in order
+fn histogram(
+    old: &[String],
+    new: &[String],
+    offset: (usize, usize),
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    let (prefix, suffix) = match_ends(old, new, offset, pairs);
+    let inner_old = &old[prefix..old.len() - suffix];
+    let inner_new = &new[prefix..new.len() - suffix];
+    let inner_offset = (offset.0 + prefix, offset.1 + prefix);
+
+    if !inner_old.is_empty() && !inner_new.is_empty() {
+        let mut occurrences: HashMap<&String, Vec<usize>> = HashMap::new();
+        for (index, line) in inner_old.iter().enumerate() {
+            occurrences.entry(line).or_default().push(index);
+        }
+
+        // The best region is the one whose rarest line is rarest in `old`, then the longest.
+        // Each region is stored as (start in old, start in new, length, occurrences).
+        let mut best: Option<(usize, usize, usize, usize)> = None;
+        for (new_index, line) in inner_new.iter().enumerate() {
+            let Some(old_indices) = occurrences.get(line) else {
+                continue;
+            };
+            if best.is_some_and(|(_, _, _, count)| old_indices.len() > count) {
+                continue;
+            }
+
+            for &old_index in old_indices {
+                let mut start = 0;
+                while start < old_index.min(new_index)
+                    && inner_old[old_index - start - 1] == inner_new[new_index - start - 1]
+                {
+                    start += 1;
+                }
+                let mut end = 1;
+                while old_index + end < inner_old.len()
+                    && new_index + end < inner_new.len()
+                    && inner_old[old_index + end] == inner_new[new_index + end]
+                {
+                    end += 1;
+                }
+
+                let count = (old_index - start..old_index + end)
+                    .map(|index| occurrences[&inner_old[index]].len())
+                    .min()
+                    .unwrap_or(old_indices.len());
+                let length = start + end;
+                let better = match best {
+                    None => true,
+                    Some((_, _, best_length, best_count)) => {
+                        count < best_count || (count == best_count && length > best_length)
+                    }
+                };
+                if better {
+                    best = Some((old_index - start, new_index - start, length, count));
+                }
+            }
+        }
+
+        match best {
+            None => myers(inner_old, inner_new, inner_offset, pairs),
+            Some((old_start, new_start, length, _)) => {
+                histogram(
+                    &inner_old[..old_start],
+                    &inner_new[..new_start],
+                    inner_offset,
+                    pairs,
+                );
+                pairs.extend((0..length).map(|i| {
+                    (
+                        inner_offset.0 + old_start + i,
+                        inner_offset.1 + new_start + i,
+                    )
+                }));
+                histogram(
+                    &inner_old[old_start + length..],
+                    &inner_new[new_start + length..],
+                    (
+                        inner_offset.0 + old_start + length,
+                        inner_offset.1 + new_start + length,
+                    ),
+                    pairs,
+                );
+            }
+        }
+    }
+
+    push_suffix(old, new, offset, suffix, pairs);
+}
+
+#[derive(Clone, Copy)]
+/// Summaries that can be shown instead of the diffs themselves
+pub enum Summary {
+    /// Lines inserted and deleted per file, with a bar showing their proportion
+    Stat,
+    /// Lines inserted and deleted per file, separated by tabs for scripts
+    NumStat,
+    /// Whether each file was added, modified or deleted
+    NameStatus,
+}
+
+#[derive(Default)]
+/// Options controlling how diffs are shown
+pub struct DiffOptions {
+    /// Show a unified diff with this many lines of context, instead of the default format
+    pub unified: Option<usize>,
+    /// Show a summary of the changes, instead of the diffs
+    pub summary: Option<Summary>,
+    /// Algorithm used to find changes, instead of the one set for the repository
+    pub algorithm: Option<DiffAlgorithm>,
+    /// Show changed lines once, highlighting only the words that changed
+    pub words: Option<WordDiff>,
+    /// Whitespace changes that aren't shown
+    pub whitespace: Whitespace,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+/// Whitespace changes that can be ignored when comparing lines
+pub enum Whitespace {
+    #[default]
+    /// Every change is shown
+    Exact,
+    /// A carriage return at the end of a line is ignored, so CRLF and LF line endings compare equal
+    IgnoreCrAtEol,
+    /// All whitespace is ignored, including a missing newline at the end of a file
+    IgnoreAll,
+}
+
+#[derive(Clone, Copy, Default)]
+/// How lines are compared, and the algorithm used to line up the ones that are equal
+pub struct Comparison {
+    pub algorithm: DiffAlgorithm,
+    pub whitespace: Whitespace,
+}
+
+impl Comparison {
+    /// The part of a line that is compared, with ignored whitespace removed
+    fn key(&self, line: &str) -> String {
+        match self.whitespace {
+            Whitespace::Exact => line.to_string(),
+            Whitespace::IgnoreCrAtEol => {
+                let (text, newline) = split_newline(line);
+                let text = text.strip_suffix('\r').unwrap_or(text);
+                match newline {
+                    true => text.to_string(),
+                    false => format!("{}{}", text, NO_NEWLINE),
+                }
+            }
+            Whitespace::IgnoreAll => line
+                .chars()
+                .filter(|&c| !c.is_whitespace() && c != NO_NEWLINE)
+                .collect(),
+        }
+    }
+
+    /// Lists the changes that turn `old` into `new`, inserted lines are taken from `new` as they are
+    pub fn changes(&self, old: &[String], new: &[String]) -> Vec<Change<String>> {
+        if self.whitespace == Whitespace::Exact {
+            return self.algorithm.changes(old, new);
+        }
+
+        let old_keys: Vec<String> = old.iter().map(|line| self.key(line)).collect();
+        let new_keys: Vec<String> = new.iter().map(|line| self.key(line)).collect();
+
+        // Inserted lines are at the same index in `new` as in the file being changed.
+        self.algorithm
+            .changes(&old_keys, &new_keys)
+            .into_iter()
+            .map(|change| match change {
+                Change::Remove(index) => Change::Remove(index),
+                Change::Insert((index, _)) => Change::Insert((index, new[index].clone())),
+                Change::Update((index, _)) => Change::Update((index, new[index].clone())),
+            })
+            .collect()
+    }
+
+    /// Matches lines of `old` to unchanged lines of `new`, see `DiffAlgorithm::line_matches`
+    pub fn line_matches(&self, old: &[String], new: &[String]) -> Vec<Option<usize>> {
+        matches_from_changes(old.len(), self.changes(old, new))
+    }
+
+    /// Whether two files only differ by ignored whitespace
+    fn equal(&self, old: &[String], new: &[String]) -> bool {
+        old.len() == new.len()
+            && old
+                .iter()
+                .zip(new)
+                .all(|(old, new)| self.key(old) == self.key(new))
+    }
+}
+
+#[derive(Clone, Copy)]
+/// Ways of highlighting words that changed within a line
+pub enum WordDiff {
+    /// Removed words are shown as `[-removed-]` and inserted words as `{+inserted+}`
+    Plain,
+    /// Removed and inserted words are coloured, without markers
+    Color,
+}
+
+/// Changes made to a single file, as shown by a summary
+struct FileStat {
+    file: String,
+    status: char,
+    insertions: usize,
+    deletions: usize,
+    /// Sizes of the old and new versions of a binary file, which has no lines to count
+    binary_sizes: Option<(usize, usize)>,
+}
+
+impl FileStat {
+    fn new(
+        file: &Path,
+        old: &Option<Vec<String>>,
+        new: &Option<Vec<String>>,
+        comparison: Comparison,
+    ) -> Self {
+        let empty = Vec::new();
+        let changes = comparison.changes(
+            old.as_ref().unwrap_or(&empty),
+            new.as_ref().unwrap_or(&empty),
+        );
+        let (insertions, deletions) =
+            changes
+                .iter()
+                .fold((0, 0), |(insertions, deletions), change| match change {
+                    Change::Remove(_) => (insertions, deletions + 1),
+                    Change::Insert(_) => (insertions + 1, deletions),
+                    Change::Update(_) => (insertions + 1, deletions + 1),
+                });
+
+        FileStat {
+            file: file.display().to_string(),
+            status: file_status(old, new),
+            insertions,
+            deletions,
+            binary_sizes: None,
+        }
+    }
+
+    fn binary(file: &Path, old: &Option<Vec<u8>>, new: &Option<Vec<u8>>) -> Self {
+        let size = |contents: &Option<Vec<u8>>| contents.as_ref().map_or(0, Vec::len);
+
+        FileStat {
+            file: file.display().to_string(),
+            status: file_status(old, new),
+            insertions: 0,
+            deletions: 0,
+            binary_sizes: Some((size(old), size(new))),
+        }
+    }
+}
+
+/// Whether a file was added, deleted or modified, given whether it existed on each side
+fn file_status<T>(old: &Option<T>, new: &Option<T>) -> char {
+    match (old, new) {
+        (None, _) => 'A',
+        (_, None) => 'D',
+        _ => 'M',
+    }
+}
+
+/// File in the repository's root, listing patterns of files to always or never diff as binary
+const KATTRIBUTES: &str = ".kattributes";
+
+/// Only this many bytes at the start of a file are checked for NUL bytes
+const BINARY_CHECK_LENGTH: usize = 8000;
+
+/// Patterns read from `.kattributes`, each followed by `binary` or `-binary`
+/// A pattern without a `/` is matched against file names, otherwise against the whole path.
+struct Attributes {
+    patterns: Vec<(Pattern, bool)>,
+}
+
+impl Attributes {
+    fn read(root: &Path) -> Self {
+        let mut patterns: Vec<(Pattern, bool)> = Vec::new();
+
+        if let Ok(contents) = fs::read(root.join(KATTRIBUTES)) {
+            for line in String::from_utf8_lossy(&contents).lines() {
+                let mut fields = line.split_whitespace();
+                let (Some(pattern), Some(attribute)) = (fields.next(), fields.next()) else {
+                    continue;
+                };
+                if pattern.starts_with('#') {
+                    continue;
+                }
+
+                let binary = match attribute {
+                    "binary" => true,
+                    "-binary" => false,
+                    _ => continue,
+                };
+                if let Ok(pattern) = Pattern::new(pattern.trim_start_matches('/')) {
+                    patterns.push((pattern, binary));
+                }
+            }
+        }
+
+        Attributes { patterns }
+    }
+
+    /// Whether the last pattern matching a file marks it as binary, or `None` if no pattern matches
+    fn binary(&self, file: &Path) -> Option<bool> {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|(pattern, _)| match pattern.as_str().contains('/') {
+                true => pattern.matches_path(file),
+                false => file
+                    .file_name()
+                    .is_some_and(|name| pattern.matches(&name.to_string_lossy())),
+            })
+            .map(|(_, binary)| *binary)
+    }
+}
+
+/// Guesses whether contents are binary, if they have NUL bytes or aren't valid UTF-8
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents
+        .iter()
+        .take(BINARY_CHECK_LENGTH)
+        .any(|&byte| byte == 0)
+        || std::str::from_utf8(contents).is_err()
+}
+
+/// Splits contents into lines, replacing anything that isn't valid UTF-8
+/// Carriage returns are kept, and a last line without a newline is marked with `NO_NEWLINE`.
+pub fn to_lines(contents: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(contents)
+        .split_inclusive('\n')
+        .map(|line| match line.strip_suffix('\n') {
+            Some(line) => line.to_string(),
+            None => format!("{}{}", line, NO_NEWLINE),
+        })
+        .collect()
+}
+
+/// Joins lines into a file's contents, the opposite of `to_lines`
+pub fn from_lines(lines: &[String]) -> Vec<u8> {
+    let mut contents = String::new();
+    for line in lines {
+        let (text, newline) = split_newline(line);
+        contents.push_str(text);
+        if newline {
+            contents.push('\n');
+        }
+    }
+    contents.into_bytes()
+}
+
+/// Marks a line that isn't followed by a newline, which can only be the last line of a file
+/// Lines are split at newlines, so they never contain one of their own to mistake for the marker.
+pub const NO_NEWLINE: char = '\n';
+
+/// Shown after a line that isn't followed by a newline, as `patch` and `git apply` expect
+const NO_NEWLINE_MESSAGE: &str = "\\ No newline at end of file";
+
+/// Separates a line's text from the `NO_NEWLINE` marker, returning whether it is followed by a newline
+pub fn split_newline(line: &str) -> (&str, bool) {
+    match line.strip_suffix(NO_NEWLINE) {
+        Some(text) => (text, false),
+        None => (line, true),
+    }
+}
+
+/// Formats a line to be read, with carriage returns and a missing newline made visible
+fn show(line: &str) -> String {
+    let (text, newline) = split_newline(line);
+    let text = text.replace('\r', "^M");
+    match newline {
+        true => text,
+        false => format!("{}\n{}", text, NO_NEWLINE_MESSAGE),
+    }
+}
+
+/// Describes how a binary file changed, by its size and hash
+fn describe_binary(old: &Option<Vec<u8>>, new: &Option<Vec<u8>>) -> String {
+    let size = |contents: &Option<Vec<u8>>| contents.as_ref().map_or(0, Vec::len);
+    let hash = |contents: &Option<Vec<u8>>| match contents {
+        Some(contents) => hash_object(contents)[..8].to_string(),
+        None => String::from("00000000"),
+    };
+
+    format!(
+        "{} -> {} bytes, {} -> {}",
+        size(old),
+        size(new),
+        hash(old),
+        hash(new)
+    )
+}
+
+/// One side of a diff, either a snapshot or the tracked files in the working tree
+pub enum Source<'a> {
+    Snapshot(&'a Tree),
+    WorkingTree(Vec<&'a PathBuf>),
+}
+
+impl Source<'_> {
+    fn files(&self) -> Vec<&PathBuf> {
+        match self {
+            Source::Snapshot(tree) => tree.files().map(|(file, _)| file).collect(),
+            Source::WorkingTree(files) => files.to_vec(),
+        }
+    }
+
+    /// Reads a file's contents, or returns `None` if this side doesn't have the file
+    fn read(&self, path: &Paths, file: &PathBuf) -> Result<Option<Vec<u8>>, Error> {
+        match self {
+            Source::Snapshot(tree) => match tree.get(file) {
+                Some(hash) => Ok(Some(read_object(path, hash)?)),
+                None => Ok(None),
+            },
+            Source::WorkingTree(files) => {
+                let file_path = path.root().join(file);
+                if !files.contains(&file) || !file_path.is_file() {
+                    return Ok(None);
+                }
+                Ok(Some(fs::read(file_path).map_err(Error::ReadFile)?))
+            }
+        }
+    }
+}
+
+/// Checks whether a file is at, or inside, one of the given paths. No paths means every file matches.
+fn matches_filters(file: &Path, filters: &[PathBuf]) -> bool {
+    filters.is_empty()
+        || filters.iter().any(|filter| {
+            let filter: PathBuf = filter
+                .components()
+                .filter(|c| !matches!(c, Component::CurDir))
+                .collect();
+            file.starts_with(filter)
+        })
+}
+
+/// Shows diffs for every file in either source, optionally only those inside `filters`
+pub fn diff_sources(
+    path: &Paths,
+    old: &Source,
+    new: &Source,
+    filters: &[PathBuf],
+    options: &DiffOptions,
+    algorithm: DiffAlgorithm,
+    output: &mut dyn Output,
+) -> Result<(), Error> {
+    let mut files = old.files();
+    files.extend(new.files());
+    files.sort();
+    files.dedup();
+
+    let mut stats: Vec<FileStat> = Vec::new();
+    let attributes = Attributes::read(&path.root());
+    let comparison = Comparison {
+        algorithm,
+        whitespace: options.whitespace,
+    };
+
+    for file in files {
+        if !matches_filters(file, filters) {
+            continue;
+        }
+
+        let old_file = old.read(path, file)?;
+        let new_file = new.read(path, file)?;
+        if old_file == new_file {
+            continue;
+        }
+
+        let old_name = match old_file {
+            Some(_) => format!("a/{}", file.display()),
+            None => String::from("/dev/null"),
+        };
+        let new_name = match new_file {
+            Some(_) => format!("b/{}", file.display()),
+            None => String::from("/dev/null"),
+        };
+
+        // Binary files are never split into lines, only their sizes and hashes are compared.
+        let binary = attributes.binary(file).unwrap_or_else(|| {
+            [&old_file, &new_file]
+                .into_iter()
+                .flatten()
+                .any(|contents| is_binary(contents))
+        });
+        if binary {
+            match (options.summary, options.unified) {
+                (Some(_), _) => stats.push(FileStat::binary(file, &old_file, &new_file)),
+                (None, Some(_)) => output.add(format!(
+                    "Binary files {} and {} differ ({})",
+                    old_name,
+                    new_name,
+                    describe_binary(&old_file, &new_file)
+                )),
+                (None, None) => {
+                    output.add(file.display().to_string());
+                    output.add(format!(
+                        "Binary files differ ({})",
+                        describe_binary(&old_file, &new_file)
+                    ));
+                    output.add_str("");
+                }
+            }
+            continue;
+        }
+
+        let old_file = old_file.as_deref().map(to_lines);
+        let new_file = new_file.as_deref().map(to_lines);
+        if let (Some(old_lines), Some(new_lines)) = (&old_file, &new_file) {
+            if comparison.equal(old_lines, new_lines) {
+                continue;
+            }
+        }
+
+        if options.summary.is_some() {
+            stats.push(FileStat::new(file, &old_file, &new_file, comparison));
+            continue;
+        }
+
+        match options.unified {
+            Some(context) => {
+                generate_unified_diff(
+                    (&old_name, &new_name),
+                    &old_file.unwrap_or_default(),
+                    &new_file.unwrap_or_default(),
+                    context,
+                    comparison,
+                    options.words,
+                    output,
+                );
+            }
+            None => {
+                output.add(file.display().to_string());
+                generate_diffs(
+                    old_file.unwrap_or_default(),
+                    new_file.unwrap_or_default(),
+                    comparison,
+                    options.words,
+                    output,
+                )?;
+            }
+        }
+    }
+
+    if let Some(summary) = options.summary {
+        generate_summary(&stats, summary, output);
+    }
+
+    Ok(())
+}
+
+/// The widest a bar in `--stat` output can be, longer bars are scaled down to this
+const STAT_WIDTH: usize = 50;
+
+/// Shows a summary of changes to files, followed by the totals
+fn generate_summary(stats: &[FileStat], summary: Summary, output: &mut dyn Output) {
+    if stats.is_empty() {
+        return;
+    }
+
+    let name_width = stats.iter().map(|stat| stat.file.len()).max().unwrap_or(0);
+    let most_changes = stats
+        .iter()
+        .map(|stat| stat.insertions + stat.deletions)
+        .max()
+        .unwrap_or(0);
+    let count_width = most_changes.to_string().len();
+
+    for stat in stats {
+        output.add(match (summary, stat.binary_sizes) {
+            (Summary::Stat, Some((old_size, new_size))) => format!(
+                " {:name_width$} | Bin {} -> {} bytes",
+                stat.file, old_size, new_size
+            ),
+            (Summary::NumStat, Some(_)) => format!("-\t-\t{}", stat.file),
+            (Summary::Stat, None) => {
+                let (mut plus, mut minus) = (stat.insertions, stat.deletions);
+                if most_changes > STAT_WIDTH {
+                    // Each side keeps at least one character, so small changes aren't hidden.
+                    let scale = |count: usize| match count {
+                        0 => 0,
+                        _ => (count * STAT_WIDTH / most_changes).max(1),
+                    };
+                    (plus, minus) = (scale(plus), scale(minus));
+                }
+                format!(
+                    " {:name_width$} | {:>count_width$} {}{}",
+                    stat.file,
+                    stat.insertions + stat.deletions,
+                    "+".repeat(plus),
+                    "-".repeat(minus),
+                )
+            }
+            (Summary::NumStat, None) => {
+                format!("{}\t{}\t{}", stat.insertions, stat.deletions, stat.file)
+            }
+            (Summary::NameStatus, _) => format!("{}\t{}", stat.status, stat.file),
+        });
+    }
+
+    // Tab separated output is meant for scripts, which can add up the counts themselves.
+    if let Summary::NumStat | Summary::NameStatus = summary {
+        return;
+    }
+
+    let insertions: usize = stats.iter().map(|stat| stat.insertions).sum();
+    let deletions: usize = stats.iter().map(|stat| stat.deletions).sum();
+    let plural = |count: usize, one: &str, many: &str| match count {
+        1 => format!("{} {}", count, one),
+        _ => format!("{} {}", count, many),
+    };
+
+    let mut total = format!(" {} changed", plural(stats.len(), "file", "files"));
+    if insertions > 0 || deletions == 0 {
+        total.push_str(&format!(
+            ", {}",
+            plural(insertions, "insertion(+)", "insertions(+)")
+        ));
+    }
+    if deletions > 0 {
+        total.push_str(&format!(
+            ", {}",
+            plural(deletions, "deletion(-)", "deletions(-)")
+        ));
+    }
+    output.add(total);
+}
+
+#[derive(Debug, PartialEq)]
+/// A step in turning one file into another, holding indices of lines in the old and new files
+enum Edit {
+    Keep(usize, usize),
+    Remove(usize),
+    Insert(usize),
+}
+
+/// Lists every line of both files as kept, removed or inserted, in the order they appear
+fn edit_script(old: &[String], new: &[String], comparison: Comparison) -> Vec<Edit> {
+    let matches = comparison.line_matches(old, new);
+    let mut edits: Vec<Edit> = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+
+    while old_index < old.len() || new_index < new.len() {
+        match matches.get(old_index) {
+            Some(Some(matched)) if *matched == new_index => {
+                edits.push(Edit::Keep(old_index, new_index));
+                old_index += 1;
+                new_index += 1;
+            }
+            Some(None) => {
+                edits.push(Edit::Remove(old_index));
+                old_index += 1;
+            }
+            // The old line is kept further along, or the old file has ended.
+            _ => {
+                edits.push(Edit::Insert(new_index));
+                new_index += 1;
+            }
+        }
+    }
+
+    edits
+}
+
+/// Formats a hunk's range for its header, `before` is the number of lines preceding the hunk
+fn hunk_range(before: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", before),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{}", before + 1, count),
+    }
+}
+
+/// Shows changes as a unified diff, which can be used with `patch` and `git apply`
+/// `names` are the old and new names of the file, used in the `---` and `+++` headers.
+pub fn generate_unified_diff(
+    names: (&str, &str),
+    old: &[String],
+    new: &[String],
+    context: usize,
+    comparison: Comparison,
+    words: Option<WordDiff>,
+    output: &mut dyn Output,
+) {
+    let edits = edit_script(old, new, comparison);
+    let changed: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Keep(_, _)))
+        .map(|(index, _)| index)
+        .collect();
+
+    if changed.is_empty() {
+        return;
+    }
+
+    output.add(format!("--- {}", names.0));
+    output.add(format!("+++ {}", names.1));
+
+    // Changes close enough for their context to overlap are shown in the same hunk.
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for &index in &changed {
+        match groups.last_mut() {
+            Some((_, last)) if index - *last <= 2 * context + 1 => *last = index,
+            _ => groups.push((index, index)),
+        }
+    }
+
+    let counts = |edits: &[Edit]| {
+        edits.iter().fold((0, 0), |(old, new), edit| match edit {
+            Edit::Keep(_, _) => (old + 1, new + 1),
+            Edit::Remove(_) => (old + 1, new),
+            Edit::Insert(_) => (old, new + 1),
+        })
+    };
+
+    for (first, last) in groups {
+        let start = first.saturating_sub(context);
+        let end = (last + context + 1).min(edits.len());
+
+        let (old_before, new_before) = counts(&edits[..start]);
+        let (old_count, new_count) = counts(&edits[start..end]);
+
+        output.add(format!(
+            "@@ -{} +{} @@",
+            hunk_range(old_before, old_count),
+            hunk_range(new_before, new_count)
+        ));
+
+        if let Some(words) = words {
+            generate_word_diff_hunk(&edits[start..end], old, new, words, comparison, output);
+            continue;
+        }
+
+        for edit in &edits[start..end] {
+            let (prefix, line) = match edit {
+                Edit::Keep(old_index, _) => (' ', &old[*old_index]),
+                Edit::Remove(old_index) => ('-', &old[*old_index]),
+                Edit::Insert(new_index) => ('+', &new[*new_index]),
+            };
+
+            // Carriage returns are kept, so the diff can still be applied to files with CRLF line endings.
+            let (text, newline) = split_newline(line);
+            output.add(format!("{}{}", prefix, text));
+            if !newline {
+                output.add_str(NO_NEWLINE_MESSAGE);
+            }
+        }
+    }
+}
+
+/// Shows a hunk with each removed line merged into the line inserted in its place
+/// Lines have no `+`, `-` or ` ` prefix, changes are only shown by highlighting.
+fn generate_word_diff_hunk(
+    edits: &[Edit],
+    old: &[String],
+    new: &[String],
+    words: WordDiff,
+    comparison: Comparison,
+    output: &mut dyn Output,
+) {
+    let mut index = 0;
+    while index < edits.len() {
+        if let Edit::Keep(old_index, _) = edits[index] {
+            output.add(show(&old[old_index]));
+            index += 1;
+            continue;
+        }
+
+        // A run of removed lines, followed by the lines inserted in their place
+        let removed: Vec<usize> = edits[index..]
+            .iter()
+            .map_while(|edit| match edit {
+                Edit::Remove(old_index) => Some(*old_index),
+                _ => None,
+            })
+            .collect();
+        index += removed.len();
+        let inserted: Vec<usize> = edits[index..]
+            .iter()
+            .map_while(|edit| match edit {
+                Edit::Insert(new_index) => Some(*new_index),
+                _ => None,
+            })
+            .collect();
+        index += inserted.len();
+
+        for pair in 0..removed.len().max(inserted.len()) {
+            output.add(match (removed.get(pair), inserted.get(pair)) {
+                (Some(&old_index), Some(&new_index)) => {
+                    diff_words(&old[old_index], &new[new_index], words, comparison)
+                }
+                (Some(&old_index), None) => highlight(&show(&old[old_index]), false, words),
+                (None, Some(&new_index)) => highlight(&show(&new[new_index]), true, words),
+                (None, None) => unreachable!("Pairs are only taken while either run has lines."),
+            });
+        }
+    }
+}
+
+#[derive(PartialEq)]
+/// Kinds of characters, a word is a run of characters of the same kind
+enum CharKind {
+    Word,
+    Space,
+    /// Punctuation is never joined with the characters next to it
+    Other,
+}
+
+impl CharKind {
+    fn of(c: char) -> Self {
+        if c.is_alphanumeric() || c == '_' {
+            CharKind::Word
+        } else if c.is_whitespace() {
+            CharKind::Space
+        } else {
+            CharKind::Other
+        }
+    }
+}
+
+/// Splits a line into words, whitespace and punctuation, which join back into the same line
+fn split_words(line: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for c in line.chars() {
+        let kind = CharKind::of(c);
+        let continues = current
+            .chars()
+            .last()
+            .is_some_and(|last| kind != CharKind::Other && CharKind::of(last) == kind);
+        if !continues && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+
+    words
+}
+
+/// Marks text as removed or inserted
+fn highlight(text: &str, inserted: bool, words: WordDiff) -> String {
+    match (words, inserted) {
+        (WordDiff::Plain, false) => format!("[-{}-]", text),
+        (WordDiff::Plain, true) => format!("{{+{}+}}", text),
+        (WordDiff::Color, false) => format!("\x1B[91m{}\x1B[0m", text),
+        (WordDiff::Color, true) => format!("\x1B[32m{}\x1B[0m", text),
+    }
+}
+
+/// Merges two versions of a line, highlighting the words that were removed and inserted
+fn diff_words(old: &str, new: &str, words: WordDiff, comparison: Comparison) -> String {
+    let ((old, _), (new, newline)) = (split_newline(old), split_newline(new));
+    let (old_words, new_words) = (split_words(old), split_words(new));
+    let mut line = String::new();
+    let (mut removed, mut inserted) = (String::new(), String::new());
+
+    let flush = |line: &mut String, removed: &mut String, inserted: &mut String| {
+        if !removed.is_empty() {
+            line.push_str(&highlight(&std::mem::take(removed), false, words));
+        }
+        if !inserted.is_empty() {
+            line.push_str(&highlight(&std::mem::take(inserted), true, words));
+        }
+    };
+
+    for edit in edit_script(&old_words, &new_words, comparison) {
+        match edit {
+            Edit::Keep(old_index, _) => {
+                flush(&mut line, &mut removed, &mut inserted);
+                line.push_str(&old_words[old_index]);
+            }
+            Edit::Remove(old_index) => removed.push_str(&old_words[old_index]),
+            Edit::Insert(new_index) => inserted.push_str(&new_words[new_index]),
+        }
+    }
+    flush(&mut line, &mut removed, &mut inserted);
+
+    let line = line.replace('\r', "^M");
+    match newline {
+        true => line,
+        false => format!("{}\n{}", line, NO_NEWLINE_MESSAGE),
+    }
 }
 
 fn generate_output_from_diffs(
     mut snapped_file: Vec<String>,
     changes: Vec<slice_diff_patch::Change<String>>,
+    comparison: Comparison,
+    words: Option<WordDiff>,
     output: &mut dyn Output,
 ) -> Result<(), Error> {
     let mut line_numbers: Vec<usize> = (1..=snapped_file.len()).collect();
@@ -48,7 +1194,7 @@ fn generate_output_from_diffs(
                 format!(
                     "\x1B[91m- {}\t|{}\x1B[0m",
                     line_numbers.remove(index),
-                    snapped_file.remove(index)
+                    show(&snapped_file.remove(index))
                 )
             }
             slice_diff_patch::Change::Insert((index, element)) => {
@@ -57,25 +1203,35 @@ fn generate_output_from_diffs(
                 // There could be an enum instead, but there really isn't any need for it.
                 line_numbers.insert(index, 0);
                 snapped_file.insert(index, element.clone());
-                format!("\x1B[32m+ {}\t|{}\x1B[0m", (index + 1), element)
+                format!("\x1B[32m+ {}\t|{}\x1B[0m", (index + 1), show(&element))
             }
             slice_diff_patch::Change::Update((index, element)) => {
                 let removed = snapped_file
                     .get(index)
                     .expect("Diffs were just calculated, this index should exist.")
                     .clone();
-                format!(
-                    "\x1B[91m- {}\t|{}\x1B[0m\n\x1B[32m+ {}\t|{}\x1B[0m",
-                    line_numbers
-                        .get(index)
-                        .expect("Diffs were just calculated, this index should exist."),
-                    removed,
-                    (&index + 1),
-                    {
-                        snapped_file[index] = element.clone();
-                        element
-                    },
-                )
+
+                let line_number = line_numbers
+                    .get(index)
+                    .expect("Diffs were just calculated, this index should exist.");
+
+                let line = match words {
+                    // The line is shown once, with only the words that changed highlighted.
+                    Some(words) => format!(
+                        "~ {}\t|{}",
+                        line_number,
+                        diff_words(&removed, &element, words, comparison)
+                    ),
+                    None => format!(
+                        "\x1B[91m- {}\t|{}\x1B[0m\n\x1B[32m+ {}\t|{}\x1B[0m",
+                        line_number,
+                        show(&removed),
+                        (&index + 1),
+                        show(&element),
+                    ),
+                };
+                snapped_file[index] = element;
+                line
 
                 // Setting the element to zero has no use, but it could be helpful while debugging.
                 // line_numbers[index] = 0;
@@ -91,8 +1247,175 @@ fn generate_output_from_diffs(
 
 #[cfg(test)]
 mod tests {
-    use super::generate_diffs;
+    use super::{
+        diff_words, from_lines, generate_diffs, generate_summary, generate_unified_diff, is_binary,
+        split_words, to_lines, Attributes, Comparison, DiffAlgorithm, FileStat, Summary, WordDiff,
+    };
     use crate::output::{DebugOutput, Output};
+    use glob::Pattern;
+    use std::path::Path;
+
+    #[test]
+    fn test_diff_algorithms() {
+        let lines = |text: &str| text.split(' ').map(String::from).collect::<Vec<String>>();
+        let cases = [
+            ("a b c d", "d b c a"),
+            ("{ a } { b }", "{ b }"),
+            ("x y x y x", "y x y"),
+            ("a b c", ""),
+            ("", "a b c"),
+            ("a a a b", "b a a a"),
+        ];
+
+        for algorithm in [
+            DiffAlgorithm::Default,
+            DiffAlgorithm::Myers,
+            DiffAlgorithm::Patience,
+            DiffAlgorithm::Histogram,
+        ] {
+            for (old, new) in cases {
+                let (old, new) = (lines(old), lines(new));
+                let changes = algorithm.changes(&old, &new);
+                assert_eq!(
+                    new,
+                    slice_diff_patch::patch(&old, &changes),
+                    "{:?}",
+                    algorithm
+                );
+            }
+        }
+
+        // The rarest lines are aligned first, instead of the first brace that matches.
+        let (old, new) = (lines("x { a } { b } y"), lines("z { b } w"));
+        let expected = vec![None, None, None, None, Some(1), Some(2), Some(3), None];
+        assert_eq!(expected, DiffAlgorithm::Histogram.line_matches(&old, &new));
+        assert_eq!(expected, DiffAlgorithm::Patience.line_matches(&old, &new));
+    }
+
+    #[test]
+    fn test_word_diff() {
+        let old = "let total = price * count;";
+        let new = "let total = price * quantity + shipping;";
+
+        assert_eq!(old, split_words(old).concat());
+        assert_eq!(
+            "let total = price * [-count-]{+quantity + shipping+};",
+            diff_words(old, new, WordDiff::Plain, Comparison::default())
+        );
+        assert_eq!(
+            "let total = price * \x1B[91mcount\x1B[0m\x1B[32mquantity + shipping\x1B[0m;",
+            diff_words(old, new, WordDiff::Color, Comparison::default())
+        );
+    }
+
+    #[test]
+    fn test_binary_detection() {
+        assert!(is_binary(b"GIF89a\x00\x01"));
+        assert!(is_binary(b"caf\xe9"));
+        assert!(!is_binary("café\n".as_bytes()));
+
+        let attributes = Attributes {
+            patterns: vec![
+                (Pattern::new("*.svg").unwrap(), true),
+                (Pattern::new("assets/*.svg").unwrap(), false),
+            ],
+        };
+        assert_eq!(Some(true), attributes.binary(Path::new("icons/logo.svg")));
+        assert_eq!(Some(false), attributes.binary(Path::new("assets/logo.svg")));
+        assert_eq!(None, attributes.binary(Path::new("logo.png")));
+    }
+
+    #[test]
+    fn test_line_round_trip() {
+        // NUL bytes are kept as text, even at the end of a line.
+        let contents: [&[u8]; 5] = [b"abc\0\n", b"abc\0", b"a\r\nb", b"a\n\n", b""];
+        for contents in contents {
+            assert_eq!(contents, from_lines(&to_lines(contents)), "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let lines = |text: &str| Some(text.lines().map(String::from).collect::<Vec<String>>());
+        let stats = vec![
+            FileStat::new(
+                Path::new("changed"),
+                &lines("a\nb\nc"),
+                &lines("a\nB\nc\nd"),
+                Comparison::default(),
+            ),
+            FileStat::new(
+                Path::new("deleted_file"),
+                &lines("a\nb"),
+                &None,
+                Comparison::default(),
+            ),
+        ];
+
+        let mut output = DebugOutput::new();
+        generate_summary(&stats, Summary::Stat, &mut output);
+        assert_eq!(
+            vec![
+                " changed      | 3 ++-",
+                " deleted_file | 2 --",
+                " 2 files changed, 2 insertions(+), 3 deletions(-)",
+            ],
+            output
+                .print()
+                .expect("generate_summary() should have given an output.")
+        );
+
+        let mut output = DebugOutput::new();
+        generate_summary(&stats, Summary::NameStatus, &mut output);
+        assert_eq!(
+            vec!["M\tchanged", "D\tdeleted_file"],
+            output
+                .print()
+                .expect("generate_summary() should have given an output.")
+        );
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let snapped_file: Vec<String> = (1..=12).map(|n| n.to_string()).collect();
+        let mut changed_file = snapped_file.clone();
+        changed_file.remove(1);
+        changed_file.insert(9, String::from("inserted"));
+
+        let mut output = DebugOutput::new();
+        generate_unified_diff(
+            ("a/numbers", "b/numbers"),
+            &snapped_file,
+            &changed_file,
+            2,
+            Comparison::default(),
+            None,
+            &mut output,
+        );
+
+        let expected = vec![
+            "--- a/numbers",
+            "+++ b/numbers",
+            "@@ -1,4 +1,3 @@",
+            " 1",
+            "-2",
+            " 3",
+            " 4",
+            "@@ -9,4 +8,5 @@",
+            " 9",
+            " 10",
+            "+inserted",
+            " 11",
+            " 12",
+        ];
+
+        assert_eq!(
+            expected,
+            output
+                .print()
+                .expect("generate_unified_diff() should have given an output.")
+        );
+    }
 
     #[test]
     fn test_diffs() {
@@ -143,7 +1466,14 @@ mod tests {
         }
 
         let mut output = DebugOutput::new();
-        assert!(generate_diffs(snapped_strings, changed_strings, &mut output).is_ok());
+        assert!(generate_diffs(
+            snapped_strings,
+            changed_strings,
+            Comparison::default(),
+            None,
+            &mut output
+        )
+        .is_ok());
 
         assert_eq!(
             test_strings,
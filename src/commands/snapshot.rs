@@ -1,27 +1,25 @@
 use crate::commands::common::get_user;
+use crate::commands::metafiles::{FileCache, Paths, Snapshots, Tree};
+use crate::commands::objects::{store_file, write_tree};
 use crate::errors::Error;
+use std::env;
 use std::fs;
+use std::io;
 use std::path::PathBuf;
+use std::process::Command;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn snap_file(file_name: &PathBuf, snap_dir: &PathBuf) -> Result<(), Error> {
-    fs::create_dir_all(snap_dir).map_err(Error::CreateDirectory)?;
+/// Stores the file's contents in the object store and records it in the tree
+pub fn snap_file(file_name: &PathBuf, path: &Paths, tree: &mut Tree) -> Result<(), Error> {
+    let hash = store_file(path, file_name)?;
+    tree.insert(file_name.to_owned(), hash);
 
-    if let Some(dir) = file_name.parent() {
-        fs::create_dir_all(snap_dir.join(dir)).map_err(Error::CreateDirectory)?;
-    }
-
-    match fs::copy(file_name, snap_dir.join(file_name)) {
-        Ok(_) => Ok(()),
-        Err(io_error) => Err(Error::FileCopy(
-            file_name.to_owned(),
-            snap_dir.join(file_name).to_owned(),
-            io_error,
-        )),
-    }
+    Ok(())
 }
 
-pub fn gen_name() -> Result<String, Error> {
+/// Generates a name from the user's name and the current time
+/// A suffix is added if a snapshot with the same name already exists.
+pub fn gen_name(snapshots: &Snapshots) -> Result<String, Error> {
     let user = get_user()?;
     // let email = String::from("test@testing.com");
 
@@ -30,5 +28,109 @@ pub fn gen_name() -> Result<String, Error> {
         .expect("Right now is before 1970? Check the system clock.")
         .as_secs();
 
-    Ok(format!("{}_{}", user.name(), current_timestamp))
+    let name = format!("{}_{}", user.name(), current_timestamp);
+    let mut unique_name = name.clone();
+    let mut suffix = 1;
+    while snapshots.contains(&unique_name) {
+        unique_name = format!("{}_{}", name, suffix);
+        suffix += 1;
+    }
+
+    Ok(unique_name)
+}
+
+/// Saves the contents of all tracked files as a snapshot that isn't on any branch
+/// Its name is returned, so the files can be brought back with `kifi restore`.
+pub fn stash(
+    path: &Paths,
+    cache: &FileCache,
+    snapshots: &mut Snapshots,
+    parent: Option<String>,
+    message: String,
+) -> Result<String, Error> {
+    let name = format!("stash_{}", gen_name(snapshots)?);
+
+    let mut tree = Tree::new();
+    for file in cache.get_tracked_files() {
+        snap_file(file, path, &mut tree)?;
+    }
+    let tree_hash = write_tree(path, &tree)?;
+
+    let user = get_user()?;
+    snapshots.new_snap(
+        &name,
+        &user,
+        tree_hash,
+        message,
+        parent.into_iter().collect(),
+    );
+
+    Ok(name)
+}
+
+/// Gets the snapshot message from `-m`, from a file given with `-F`, or by opening an editor
+pub fn get_message(
+    message: &Option<String>,
+    message_file: &Option<PathBuf>,
+    path: &Paths,
+) -> Result<String, Error> {
+    let message = match (message, message_file) {
+        (Some(message), _) => message.to_owned(),
+        (None, Some(file)) => fs::read_to_string(file).map_err(Error::ReadFile)?,
+        (None, None) => edit_message(path)?,
+    };
+
+    let message = message.trim().to_string();
+    if message.is_empty() {
+        return Err(Error::EmptyMessage);
+    }
+
+    Ok(message)
+}
+
+/// Opens the user's editor on a temporary file in .kifi and returns what was written, without comments
+fn edit_message(path: &Paths) -> Result<String, Error> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .map_err(|_| Error::EditorNotSet)?;
+
+    // The command is split like a shell would, so that values such as "code --wait" work.
+    let mut words = shell_words::split(&editor)
+        .map_err(|e| {
+            Error::RunEditor(
+                editor.clone(),
+                io::Error::new(io::ErrorKind::InvalidInput, e),
+            )
+        })?
+        .into_iter();
+    let program = words.next().ok_or(Error::EditorNotSet)?;
+
+    fs::write(
+        path.message(),
+        "\n# Enter a message for this snapshot. Lines starting with '#' are ignored,\n# and an empty message aborts the snapshot.\n",
+    )
+    .map_err(Error::CreateFile)?;
+
+    let message = match Command::new(program)
+        .args(words)
+        .arg(path.message())
+        .status()
+    {
+        Ok(status) if status.success() => {
+            fs::read_to_string(path.message()).map_err(Error::ReadFile)
+        }
+        Ok(_) => Err(Error::EditorFailed(editor)),
+        Err(e) => Err(Error::RunEditor(editor, e)),
+    };
+
+    // The file is removed even if the editor failed, so it isn't left in .kifi.
+    let removed = fs::remove_file(path.message()).map_err(Error::RemoveFile);
+    let message = message?;
+    removed?;
+
+    Ok(message
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<&str>>()
+        .join("\n"))
 }
@@ -0,0 +1,173 @@
+use crate::commands::metafiles::{FileCache, FileStatus, Paths, Tree};
+use crate::commands::objects::hash_object;
+use crate::errors::Error;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+/// Files in the working tree, grouped by how they differ from a snapshot
+/// Every group is sorted by path.
+pub struct Status {
+    /// Tracked files whose contents differ from the snapshot
+    pub modified: Vec<PathBuf>,
+    /// Tracked files that are not in the snapshot
+    pub added: Vec<PathBuf>,
+    /// Files in the snapshot that were removed, or are no longer tracked
+    pub deleted: Vec<PathBuf>,
+    /// Deleted files whose contents were added unchanged at another path
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+    pub untracked: Vec<PathBuf>,
+    /// Ignored files, and ignored directories that weren't searched, ending with a separator
+    pub ignored: Vec<PathBuf>,
+}
+
+impl Status {
+    /// Compares the files in the cache to `tree`, which is `None` if no snapshot has been taken
+    pub fn new(path: &Paths, cache: &FileCache, tree: Option<&Tree>) -> Result<Self, Error> {
+        let mut status = Status {
+            modified: Vec::new(),
+            added: Vec::new(),
+            deleted: Vec::new(),
+            renamed: Vec::new(),
+            untracked: Vec::new(),
+            ignored: Vec::new(),
+        };
+
+        for file in cache.get_keys() {
+            match cache.get_status(file).expect("Keys were fetched from the cache and immediately used, so the corresponding value should exist.") {
+                FileStatus::Tracked => match tree.and_then(|t| t.get(file)) {
+                    Some(hash) => {
+                        let contents = fs::read(path.root().join(file)).map_err(Error::ReadFile)?;
+                        if &hash_object(&contents) != hash {
+                            status.modified.push(file.to_owned());
+                        }
+                    }
+                    None => status.added.push(file.to_owned()),
+                },
+                FileStatus::Untracked => status.untracked.push(file.to_owned()),
+                FileStatus::Ignored => status.ignored.push(file.to_owned()),
+            }
+        }
+
+        if let Some(tree) = tree {
+            for (file, _) in tree.files() {
+                if !cache.has_tracked_file(file) {
+                    status.deleted.push(file.to_owned());
+                }
+            }
+        }
+
+        status.modified.sort();
+        status.added.sort();
+        status.deleted.sort();
+
+        // A deleted file is renamed if a tracked file with exactly the same contents was added.
+        if let Some(tree) = tree {
+            // Each added file is hashed once. Files with the same contents are paired in order of their paths.
+            let mut added_hashes: HashMap<String, Vec<PathBuf>> = HashMap::new();
+            for added in status.added.iter().rev() {
+                if let Ok(contents) = fs::read(path.root().join(added)) {
+                    added_hashes
+                        .entry(hash_object(&contents))
+                        .or_default()
+                        .push(added.to_owned());
+                }
+            }
+
+            let mut deleted: Vec<PathBuf> = Vec::new();
+            for file in status.deleted.drain(..) {
+                let hash = tree
+                    .get(&file)
+                    .expect("Deleted files were taken from the tree.");
+                match added_hashes.get_mut(hash).and_then(|added| added.pop()) {
+                    Some(added) => status.renamed.push((file, added)),
+                    None => deleted.push(file),
+                }
+            }
+            status.deleted = deleted;
+
+            let renamed: HashSet<&PathBuf> = status.renamed.iter().map(|(_, to)| to).collect();
+            status.added.retain(|added| !renamed.contains(added));
+        }
+
+        status.ignored.extend(
+            cache
+                .get_ignored_directories()
+                .iter()
+                .map(|directory| directory.join("")),
+        );
+        status.untracked.sort();
+        status.ignored.sort();
+
+        Ok(status)
+    }
+
+    /// Tracked files in the working tree whose contents aren't in the snapshot
+    pub fn changed(&self) -> impl Iterator<Item = &PathBuf> {
+        self.modified
+            .iter()
+            .chain(self.added.iter())
+            .chain(self.renamed.iter().map(|(_, to)| to))
+    }
+
+    /// Lists the changed files whose contents would be lost by writing `target` over the working tree
+    /// Files missing from `target` are only lost if they are in `current`, as `checkout_tree` removes them.
+    pub fn overwritten_by(
+        &self,
+        path: &Paths,
+        target: &Tree,
+        current: Option<&Tree>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let mut overwritten: Vec<PathBuf> = Vec::new();
+        for file in self.changed() {
+            let kept = match target.get(file) {
+                Some(hash) => {
+                    let contents = fs::read(path.root().join(file)).map_err(Error::ReadFile)?;
+                    &hash_object(&contents) == hash
+                }
+                None => current.and_then(|tree| tree.get(file)).is_none(),
+            };
+            if !kept {
+                overwritten.push(file.to_owned());
+            }
+        }
+        overwritten.sort();
+
+        Ok(overwritten)
+    }
+
+    /// Lists files in `target` that aren't in `current` or tracked, but are in the working tree with other contents
+    /// Writing `target` would replace them, and they can't be stashed since they aren't tracked.
+    pub fn untracked_overwritten_by(
+        &self,
+        path: &Paths,
+        target: &Tree,
+        current: Option<&Tree>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let changed: HashSet<&PathBuf> = self.changed().collect();
+        let mut overwritten: Vec<PathBuf> = Vec::new();
+        for (file, hash) in target.files() {
+            if changed.contains(file) || current.and_then(|tree| tree.get(file)).is_some() {
+                continue;
+            }
+            let destination = path.root().join(file);
+            if destination.is_file() {
+                let contents = fs::read(&destination).map_err(Error::ReadFile)?;
+                if &hash_object(&contents) != hash {
+                    overwritten.push(file.to_owned());
+                }
+            }
+        }
+        overwritten.sort();
+
+        Ok(overwritten)
+    }
+
+    /// Whether the next snapshot would differ from the one compared against
+    pub fn has_changes(&self) -> bool {
+        !(self.modified.is_empty()
+            && self.added.is_empty()
+            && self.deleted.is_empty()
+            && self.renamed.is_empty())
+    }
+}
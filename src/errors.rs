@@ -1,4 +1,3 @@
-use fs_extra::error::Error as dirError;
 use std::io::Error as ioError;
 use std::path::PathBuf;
 use std::time::SystemTime;
@@ -16,7 +15,6 @@ pub enum Error {
     CBORWriter(serde_cbor::Error),
     CBORReader(serde_cbor::Error),
     FileCopy(PathBuf, PathBuf, ioError),
-    DirCopy(PathBuf, PathBuf, dirError),
     FileNotFoundInCache(PathBuf), // String is the path to the file
     ReservedFilenameNotAvailable(PathBuf),
     PreviewWithoutSnapshots,
@@ -25,6 +23,31 @@ pub enum Error {
     UserNotRegistered,
     TrackIgnoredFile(PathBuf),
     InvalidTime(SystemTime),
+    ObjectNotFound(String), // String is the hash of the object
+    EmptyMessage,
+    SnapshotNotFound(String),
+    BranchExists(String),
+    BranchNotFound(String),
+    DeleteCurrentBranch(String),
+    BranchWithoutSnapshots,
+    RemoveFile(ioError),
+    MergeInProgress,
+    MergeWithoutSnapshots,
+    FileNotTracked(PathBuf),
+    DestinationExists(PathBuf),
+    FileNotInSnapshot(PathBuf, String), // String is the name of the snapshot
+    UncommittedChanges(Vec<PathBuf>),
+    UntrackedOverwritten(Vec<PathBuf>),
+    TagExists(String),
+    TagNotFound(String),
+    UnresolvedConflicts(Vec<PathBuf>),
+    EditorNotSet,
+    RunEditor(String, ioError), // String is the editor command
+    EditorFailed(String),
+    UnknownDiffAlgorithm(String),
+    UnknownConfigKey(String),
+    InvalidPatch(String), // String describes what is wrong with the patch
+    InvalidTagName(String, &'static str), // The name, and why it can't be used
 }
 
 impl Error {
@@ -66,14 +89,6 @@ impl Error {
                     io_error
                 ));
             }
-            Error::DirCopy(from, to, dir_error) => {
-                output.add(format!(
-                    "Failed to copy {} to {}: {:?}",
-                    from.display(),
-                    to.display(),
-                    dir_error
-                ));
-            }
             Error::FileNotFoundInCache(file_path) => {
                 output.add(format!("File not found in cache: {}", file_path.display()));
             }
@@ -100,6 +115,112 @@ impl Error {
             Error::InvalidTime(time) => {
                 output.add(format!("Could not parse time {:?}.", time));
             }
+            Error::ObjectNotFound(hash) => {
+                output.add(format!("Object {} is missing from the store.", hash));
+                output.add_str("The repository may be corrupted.");
+            }
+            Error::EmptyMessage => {
+                output.add_str("Aborting snapshot due to an empty message.");
+            }
+            Error::SnapshotNotFound(name) => {
+                output.add(format!("Snapshot {} not found.", name));
+            }
+            Error::BranchExists(branch) => {
+                output.add(format!("A branch named {} already exists.", branch));
+            }
+            Error::BranchNotFound(branch) => {
+                output.add(format!("Branch {} not found.", branch));
+            }
+            Error::DeleteCurrentBranch(branch) => {
+                output.add(format!(
+                    "Cannot delete {}, it is the current branch.",
+                    branch
+                ));
+                output.add_str("Use `kifi switch` to change to another branch first.");
+            }
+            Error::BranchWithoutSnapshots => {
+                output.add_str("Cannot create a branch before a snapshot has been taken.");
+            }
+            Error::RemoveFile(io_error) => {
+                output.add(format!("Failed to remove file: {:?}", io_error));
+            }
+            Error::MergeInProgress => {
+                output.add_str("A merge is already in progress.");
+                output.add_str("Resolve its conflicts and run `kifi klick` to finish it first.");
+            }
+            Error::MergeWithoutSnapshots => {
+                output.add_str("Cannot merge before a snapshot has been taken on this branch.");
+            }
+            Error::UnresolvedConflicts(files) => {
+                output.add_str("These files still have unresolved conflicts:");
+                for file in files {
+                    output.add(format!("\t{}", file.display()));
+                }
+                output.add_str(
+                    "Remove the conflict markers, and `kifi track` or `kifi rm` binary files.",
+                );
+            }
+            Error::TagExists(tag) => {
+                output.add(format!("A tag named {} already exists.", tag));
+            }
+            Error::TagNotFound(tag) => {
+                output.add(format!("Tag {} not found.", tag));
+            }
+            Error::FileNotTracked(file) => {
+                output.add(format!("{} is not tracked.", file.display()));
+            }
+            Error::DestinationExists(file) => {
+                output.add(format!("{} already exists.", file.display()));
+            }
+            Error::FileNotInSnapshot(file, snapshot) => {
+                output.add(format!(
+                    "{} does not exist in snapshot {}.",
+                    file.display(),
+                    snapshot
+                ));
+            }
+            Error::UncommittedChanges(files) => {
+                output.add_str("These files have changes that would be overwritten:");
+                for file in files {
+                    output.add(format!("\t{}", file.display()));
+                }
+                output.add_str(
+                    "Take a snapshot first. Reverting and switching branches can also stash the changes with --force, and `kifi rm --force` discards them.",
+                );
+            }
+            Error::UntrackedOverwritten(files) => {
+                output.add_str("These untracked files would be overwritten:");
+                for file in files {
+                    output.add(format!("\t{}", file.display()));
+                }
+                output.add_str("Move them, or track them and take a snapshot, first.");
+            }
+            Error::EditorNotSet => {
+                output.add_str("No editor found to write the snapshot message.");
+                output.add_str("Set $EDITOR, or use -m or -F to provide a message.");
+            }
+            Error::RunEditor(editor, io_error) => {
+                output.add(format!("Could not run editor {}: {:?}", editor, io_error));
+            }
+            Error::EditorFailed(editor) => {
+                output.add(format!("Editor {} exited unsuccessfully.", editor));
+            }
+            Error::UnknownDiffAlgorithm(name) => {
+                output.add(format!("Unknown diff algorithm '{}'.", name));
+                output.add_str("Use one of 'default', 'myers', 'patience' or 'histogram'.");
+            }
+            Error::UnknownConfigKey(key) => {
+                output.add(format!("Unknown setting '{}'.", key));
+                output.add_str(
+                    "Run `kifi config` to list the repository's settings, or `kifi config --global` for yours.",
+                );
+            }
+            Error::InvalidPatch(reason) => {
+                output.add(format!("Could not read the patch, {}.", reason));
+            }
+            Error::InvalidTagName(name, reason) => {
+                output.add(format!("'{}' is not a valid tag name, {}.", name, reason));
+            }
         }
     }
 }
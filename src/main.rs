@@ -3,8 +3,10 @@ mod errors;
 mod output;
 
 use crate::errors::Error;
-use clap::{Parser, Subcommand};
+use clap::builder::PossibleValuesParser;
+use clap::{Args, Parser, Subcommand};
 use output::{ConsoleOutput, DebugOutput, Output};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(arg_required_else_help = true)]
@@ -14,32 +16,245 @@ struct Cli {
     command: Option<Commands>,
 }
 
+#[derive(Args)]
+struct DiffArgs {
+    #[arg(short = 'u', long = "unified")]
+    /// show a unified diff, which can be used with `patch` and `git apply`
+    unified: bool,
+    #[arg(short = 'U', value_name = "LINES")]
+    /// lines of context in a unified diff, 3 by default
+    context: Option<usize>,
+    #[arg(long = "stat", group = "summary")]
+    /// show how many lines changed in each file, instead of the diffs
+    stat: bool,
+    #[arg(long = "numstat", group = "summary")]
+    /// show lines inserted and deleted in each file, separated by tabs
+    numstat: bool,
+    #[arg(long = "name-status", group = "summary")]
+    /// show only whether each file was added, modified or deleted
+    name_status: bool,
+    #[arg(long = "diff-algorithm", value_name = "ALGORITHM", value_parser = PossibleValuesParser::new(commands::DIFF_ALGORITHMS))]
+    /// algorithm used to find changes, instead of the one set with `kifi config`
+    diff_algorithm: Option<String>,
+    #[arg(long = "word-diff", group = "words")]
+    /// show changed lines once, marking removed words with [-...-] and inserted words with {+...+}
+    word_diff: bool,
+    #[arg(long = "color-words", group = "words")]
+    /// show changed lines once, colouring the words that changed
+    color_words: bool,
+    #[arg(short = 'w', long = "ignore-all-space")]
+    /// ignore whitespace when comparing lines, including line endings
+    ignore_all_space: bool,
+    #[arg(long = "ignore-cr-at-eol")]
+    /// ignore a carriage return at the end of a line, so CRLF and LF line endings compare equal
+    ignore_cr_at_eol: bool,
+}
+
+impl DiffArgs {
+    fn options(&self) -> commands::DiffOptions {
+        commands::DiffOptions {
+            unified: match (self.unified, self.context) {
+                (_, Some(context)) => Some(context),
+                (true, None) => Some(3),
+                (false, None) => None,
+            },
+            summary: match (self.stat, self.numstat, self.name_status) {
+                (true, _, _) => Some(commands::Summary::Stat),
+                (_, true, _) => Some(commands::Summary::NumStat),
+                (_, _, true) => Some(commands::Summary::NameStatus),
+                _ => None,
+            },
+            algorithm: self.diff_algorithm.as_ref().map(|name| {
+                name.parse()
+                    .expect("Only known algorithms are accepted by the argument parser.")
+            }),
+            words: match (self.word_diff, self.color_words) {
+                (true, _) => Some(commands::WordDiff::Plain),
+                (_, true) => Some(commands::WordDiff::Color),
+                _ => None,
+            },
+            whitespace: match (self.ignore_all_space, self.ignore_cr_at_eol) {
+                (true, _) => commands::Whitespace::IgnoreAll,
+                (_, true) => commands::Whitespace::IgnoreCrAtEol,
+                _ => commands::Whitespace::Exact,
+            },
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// initialises a repository
     Init,
     /// shows information about the repository
     Meta,
-    /// tracks the given file
+    /// shows modified, added, deleted and untracked files
+    Status {
+        #[arg(short = 's', long = "porcelain")]
+        /// one line per file, prefixed with a status code
+        porcelain: bool,
+        #[arg(long = "ignored")]
+        /// also show ignored files
+        show_ignored: bool,
+    },
+    /// tracks the given files, directories or glob patterns
     Track {
-        file_name: String,
+        #[arg(required_unless_present = "all")]
+        file_names: Vec<String>,
+        #[arg(long = "all", conflicts_with = "file_names")]
+        /// track every file in the repository
+        all: bool,
         #[arg(short = 'f')]
         /// force tracking ignored files
         forced: bool,
     },
+    /// stops tracking the given file, without deleting it
+    Untrack { file_name: String },
+    /// deletes the given tracked file
+    Rm {
+        file_name: String,
+        #[arg(long = "force")]
+        /// delete the file even if it has changes since the last snapshot
+        force: bool,
+    },
+    /// moves or renames the given file
+    Mv { from: String, to: String },
     /// shows diffs from the last snapshot
-    Preview,
+    Preview {
+        /// snapshot or tag to compare against, instead of the last snapshot
+        snapshot: Option<String>,
+        #[command(flatten)]
+        diff_args: DiffArgs,
+    },
+    /// shows diffs between snapshots, or a snapshot and the working tree
+    Diff {
+        /// snapshot or tag to compare from, the last snapshot by default
+        from: Option<String>,
+        /// snapshot or tag to compare to, the working tree by default
+        to: Option<String>,
+        #[arg(last = true)]
+        /// only show diffs for these files or directories
+        file_names: Vec<String>,
+        #[command(flatten)]
+        diff_args: DiffArgs,
+    },
     /// takes a snapshot of tracked files
-    Klick,
+    Klick {
+        #[arg(short = 'm', conflicts_with = "message_file")]
+        /// message describing the snapshot
+        message: Option<String>,
+        #[arg(short = 'F')]
+        /// read the message from a file
+        message_file: Option<PathBuf>,
+    },
     /// shows previous snapshots
-    Log,
+    Log {
+        /// snapshot or tag to start from, instead of the last snapshot
+        snapshot: Option<String>,
+    },
     /// reverts to a specific snapshot
-    Revert { name: String },
+    Revert {
+        name: String,
+        #[arg(long = "force")]
+        /// overwrite files with changes, after stashing them
+        force: bool,
+    },
+    /// restores the given files or directories from a snapshot
+    Restore {
+        /// snapshot or tag to restore from
+        snapshot: String,
+        #[arg(last = true, required = true)]
+        file_names: Vec<String>,
+        #[arg(long = "dry-run")]
+        /// list the files that would be restored, without changing them
+        dry_run: bool,
+    },
     #[cfg(debug_assertions)]
     /// prints contents of metadata files
     Debug,
+    /// lists, creates or deletes branches
+    Branch {
+        name: Option<String>,
+        #[arg(short = 'd', requires = "name")]
+        /// delete the branch
+        delete: bool,
+    },
+    /// switches to another branch
+    Switch {
+        branch: String,
+        #[arg(short = 'c')]
+        /// create the branch before switching to it
+        create: bool,
+        #[arg(long = "force")]
+        /// overwrite files with changes, after stashing them
+        force: bool,
+    },
+    /// merges another branch into the current one
+    Merge {
+        branch: String,
+        #[arg(short = 'm')]
+        /// message for the merge snapshot
+        message: Option<String>,
+    },
+    /// lists, creates or deletes tags
+    Tag {
+        name: Option<String>,
+        /// snapshot to tag, instead of the last snapshot
+        snapshot: Option<String>,
+        #[arg(short = 'm')]
+        /// message for an annotated tag
+        message: Option<String>,
+        #[arg(short = 'd', requires = "name", conflicts_with_all = ["snapshot", "message"])]
+        /// delete the tag
+        delete: bool,
+    },
+    /// shows which paths are ignored, and which pattern ignores them
+    CheckIgnore {
+        #[arg(required = true)]
+        /// paths relative to the repository's root, as with other commands
+        paths: Vec<String>,
+        #[arg(short = 'v', long = "verbose")]
+        /// show the pattern deciding each path, and the file and line it is from
+        verbose: bool,
+        #[arg(short = 'n', long = "non-matching", requires = "verbose")]
+        /// also list paths that no pattern matches
+        non_matching: bool,
+    },
+    /// writes a patch file for each snapshot in a range, such as `v1.2..HEAD`
+    FormatPatch {
+        /// snapshots after `from`, up to `to` or HEAD, written as `from..to`
+        range: String,
+        #[arg(short = 'o', long = "output-directory")]
+        /// directory to write the patches to, instead of the current directory
+        output_directory: Option<PathBuf>,
+    },
+    /// applies patch files to the working tree
+    Apply {
+        #[arg(required = true)]
+        patch_files: Vec<PathBuf>,
+    },
+    /// shows or changes settings for the repository, such as `diff.algorithm`
+    Config {
+        /// name of the setting, all settings are listed if this is not given
+        key: Option<String>,
+        #[arg(conflicts_with = "unset")]
+        /// new value for the setting
+        value: Option<String>,
+        #[arg(long = "unset", requires = "key")]
+        /// return the setting to its default
+        unset: bool,
+        #[arg(long = "global")]
+        /// use the user's settings, such as `user.kignore`, instead of the repository's
+        global: bool,
+    },
     /// registers user name and email
-    Register { username: String, email: String },
+    Register {
+        username: String,
+        email: String,
+        #[arg(long = "kignore")]
+        /// ignore file that applies to every repository
+        kignore: Option<PathBuf>,
+    },
 }
 
 fn main() {
@@ -50,16 +265,90 @@ fn main() {
     let exit_status: Result<(), Error> = match &cli.command {
         Some(Commands::Init) => commands::initialise(&mut output, None),
         Some(Commands::Meta) => commands::meta(&mut output, None),
-        Some(Commands::Track { file_name, forced }) => {
-            commands::track(file_name, forced, &mut output, None)
+        Some(Commands::Status {
+            porcelain,
+            show_ignored,
+        }) => commands::status(&mut output, porcelain, show_ignored, None),
+        Some(Commands::Track {
+            file_names,
+            all,
+            forced,
+        }) => commands::track(file_names, all, forced, &mut output, None),
+        Some(Commands::Untrack { file_name }) => commands::untrack(file_name, &mut output, None),
+        Some(Commands::Rm { file_name, force }) => {
+            commands::remove(file_name, force, &mut output, None)
         }
-        Some(Commands::Preview) => commands::preview(&mut output, None),
-        Some(Commands::Klick) => commands::snapshot(None),
-        Some(Commands::Log) => commands::log(&mut output, None),
-        Some(Commands::Revert { name }) => commands::revert(&mut output, name.to_owned(), None),
+        Some(Commands::Mv { from, to }) => commands::move_file(from, to, &mut output, None),
+        Some(Commands::Preview {
+            snapshot,
+            diff_args,
+        }) => commands::preview(&mut output, snapshot, &diff_args.options(), None),
+        Some(Commands::Diff {
+            from,
+            to,
+            file_names,
+            diff_args,
+        }) => commands::diff(
+            &mut output,
+            from,
+            to,
+            file_names,
+            &diff_args.options(),
+            None,
+        ),
+        Some(Commands::Klick {
+            message,
+            message_file,
+        }) => commands::snapshot(message, message_file, None),
+        Some(Commands::Log { snapshot }) => commands::log(&mut output, snapshot, None),
+        Some(Commands::Revert { name, force }) => {
+            commands::revert(&mut output, name.to_owned(), force, None)
+        }
+        Some(Commands::Restore {
+            snapshot,
+            file_names,
+            dry_run,
+        }) => commands::restore(&mut output, snapshot, file_names, dry_run, None),
         #[cfg(debug_assertions)]
         Some(Commands::Debug) => commands::debug_meta(&mut output, None),
-        Some(Commands::Register { username, email }) => commands::register(username, email),
+        Some(Commands::Branch { name, delete }) => {
+            commands::branch(&mut output, name, delete, None)
+        }
+        Some(Commands::Switch {
+            branch,
+            create,
+            force,
+        }) => commands::switch(&mut output, branch, create, force, None),
+        Some(Commands::Merge { branch, message }) => {
+            commands::merge(&mut output, branch, message, None)
+        }
+        Some(Commands::Tag {
+            name,
+            snapshot,
+            message,
+            delete,
+        }) => commands::tag(&mut output, name, snapshot, message, delete, None),
+        Some(Commands::CheckIgnore {
+            paths,
+            verbose,
+            non_matching,
+        }) => commands::check_ignore(&mut output, paths, verbose, non_matching, None),
+        Some(Commands::FormatPatch {
+            range,
+            output_directory,
+        }) => commands::format_patch(&mut output, range, output_directory, None),
+        Some(Commands::Apply { patch_files }) => commands::apply(&mut output, patch_files, None),
+        Some(Commands::Config {
+            key,
+            value,
+            unset,
+            global,
+        }) => commands::config(&mut output, key, value, unset, global, None),
+        Some(Commands::Register {
+            username,
+            email,
+            kignore,
+        }) => commands::register(username, email, kignore),
         None => {
             // This will not execute as long as the flag 'arg_required_else_help' is set to 'true'.
             unreachable!();
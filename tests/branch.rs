@@ -0,0 +1,274 @@
+mod common;
+
+use common::repository;
+use kifi::commands;
+use kifi::errors::Error;
+use kifi::output::{DebugOutput, Output};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+#[test]
+fn test_switch_with_changes() {
+    let repository = repository(&[("notes.txt", "one\n")]);
+    let root = Some(repository.path().to_path_buf());
+    let switch = |branch: &str, create: bool, force: bool| {
+        commands::switch(
+            &mut DebugOutput::new(),
+            &String::from(branch),
+            &create,
+            &force,
+            root.clone(),
+        )
+    };
+
+    switch("experiment", true, false).expect("a new branch should be created");
+    fs::write(repository.path().join("notes.txt"), "one\ntwo\n").unwrap();
+    commands::snapshot(&Some(String::from("Add two")), &None, root.clone()).unwrap();
+
+    fs::write(repository.path().join("notes.txt"), "one\ntwo\nthree\n").unwrap();
+    match switch("main", false, false) {
+        Err(Error::UncommittedChanges(files)) => {
+            assert_eq!(vec![PathBuf::from("notes.txt")], files)
+        }
+        _ => panic!("switching should refuse to overwrite changed files"),
+    }
+    assert_eq!(
+        "one\ntwo\nthree\n",
+        fs::read_to_string(repository.path().join("notes.txt")).unwrap()
+    );
+
+    switch("main", false, true).expect("forced switches should stash the changes");
+    assert_eq!(
+        "one\n",
+        fs::read_to_string(repository.path().join("notes.txt")).unwrap()
+    );
+}
+
+#[test]
+fn test_switch_over_untracked_files() {
+    let repository = repository(&[("notes.txt", "one\n")]);
+    let root = Some(repository.path().to_path_buf());
+    let switch = |branch: &str, create: bool, force: bool| {
+        commands::switch(
+            &mut DebugOutput::new(),
+            &String::from(branch),
+            &create,
+            &force,
+            root.clone(),
+        )
+    };
+
+    switch("feature", true, false).unwrap();
+    fs::write(repository.path().join("f.txt"), "feature\n").unwrap();
+    commands::track(
+        &[String::from("f.txt")],
+        &false,
+        &false,
+        &mut DebugOutput::new(),
+        root.clone(),
+    )
+    .unwrap();
+    commands::snapshot(&Some(String::from("Add f")), &None, root.clone()).unwrap();
+    switch("main", false, false).unwrap();
+
+    // Untracked files can't be stashed, so they stop the switch even when it is forced.
+    fs::write(repository.path().join("f.txt"), "mine\n").unwrap();
+    for force in [false, true] {
+        match switch("feature", false, force) {
+            Err(Error::UntrackedOverwritten(files)) => {
+                assert_eq!(vec![PathBuf::from("f.txt")], files)
+            }
+            _ => panic!("switching should refuse to overwrite untracked files"),
+        }
+    }
+    assert_eq!(
+        "mine\n",
+        fs::read_to_string(repository.path().join("f.txt")).unwrap()
+    );
+}
+
+#[test]
+fn test_switch_with_ignored_files() {
+    let repository = repository(&[(".kignore", "build/\n")]);
+    let root = Some(repository.path().to_path_buf());
+    let switch = |branch: &str, create: bool| {
+        commands::switch(
+            &mut DebugOutput::new(),
+            &String::from(branch),
+            &create,
+            &false,
+            root.clone(),
+        )
+    };
+
+    switch("feature", true).unwrap();
+    fs::create_dir(repository.path().join("build")).unwrap();
+    fs::write(repository.path().join("build/out.txt"), "out\n").unwrap();
+    commands::track(
+        &[String::from("build/out.txt")],
+        &false,
+        &true,
+        &mut DebugOutput::new(),
+        root.clone(),
+    )
+    .unwrap();
+    commands::snapshot(&Some(String::from("Add output")), &None, root.clone()).unwrap();
+
+    switch("main", false).unwrap();
+    assert!(!repository.path().join("build/out.txt").exists());
+    switch("feature", false).expect("files in ignored directories should be restored");
+
+    let mut output = DebugOutput::new();
+    commands::status(&mut output, &true, &false, root).unwrap();
+    assert!(output.print().unwrap_or_default().is_empty());
+}
+
+/// Creates a branch changing `notes.txt` and `data.bin`, and changes them differently on main
+fn diverged_repository(notes: (&str, &str, &str), data: (&[u8], &[u8], &[u8])) -> TempDir {
+    let repository = repository(&[("notes.txt", notes.0), ("data.bin", "")]);
+    let root = Some(repository.path().to_path_buf());
+    let write = |notes: &str, data: &[u8], message: &str| {
+        fs::write(repository.path().join("notes.txt"), notes).unwrap();
+        fs::write(repository.path().join("data.bin"), data).unwrap();
+        commands::snapshot(&Some(String::from(message)), &None, root.clone()).unwrap();
+    };
+    let switch = |branch: &str, create: bool| {
+        commands::switch(
+            &mut DebugOutput::new(),
+            &String::from(branch),
+            &create,
+            &false,
+            root.clone(),
+        )
+        .unwrap();
+    };
+
+    write(notes.0, data.0, "Base");
+    switch("experiment", true);
+    write(notes.2, data.2, "Theirs");
+    switch("main", false);
+    write(notes.1, data.1, "Ours");
+
+    repository
+}
+
+fn merge(repository: &TempDir) -> Result<Vec<String>, Error> {
+    let mut output = DebugOutput::new();
+    commands::merge(
+        &mut output,
+        &String::from("experiment"),
+        &None,
+        Some(repository.path().to_path_buf()),
+    )?;
+    Ok(output.print().unwrap_or_default())
+}
+
+#[test]
+fn test_merge_with_changes() {
+    let repository = diverged_repository(("a\nb\n", "A\nb\n", "a\nB\n"), (b"", b"", b""));
+    fs::write(repository.path().join("notes.txt"), "changed\n").unwrap();
+
+    match merge(&repository) {
+        Err(Error::UncommittedChanges(files)) => {
+            assert_eq!(vec![PathBuf::from("notes.txt")], files)
+        }
+        _ => panic!("merging should refuse to overwrite changed files"),
+    }
+    assert_eq!(
+        "changed\n",
+        fs::read_to_string(repository.path().join("notes.txt")).unwrap()
+    );
+}
+
+#[test]
+fn test_merge_keeps_line_endings() {
+    // `=======` on its own isn't a conflict marker, so the merge is finished.
+    let repository = diverged_repository(
+        (
+            "a\r\n=======\r\nb",
+            "A\r\n=======\r\nb",
+            "a\r\n=======\r\nB",
+        ),
+        (b"", b"", b""),
+    );
+
+    assert_eq!(
+        Some(&String::from("Merged experiment into main")),
+        merge(&repository).unwrap().last()
+    );
+    assert_eq!(
+        "A\r\n=======\r\nB",
+        fs::read_to_string(repository.path().join("notes.txt")).unwrap()
+    );
+}
+
+#[test]
+fn test_merge_binary_files() {
+    let repository = diverged_repository(
+        ("a\n", "a\n", "a\n"),
+        (&[0, 1], &[0, 2, 0xff], &[0, 3, 0xfe]),
+    );
+
+    assert_eq!(
+        vec![
+            "CONFLICT (binary): data.bin",
+            "Binary files keep this branch's version. Replace them if needed, then `kifi track` or `kifi rm` them.",
+            "Fix the conflicts, then run `kifi klick` to finish the merge.",
+        ],
+        merge(&repository).unwrap()
+    );
+    assert_eq!(
+        vec![0, 2, 0xff],
+        fs::read(repository.path().join("data.bin")).unwrap()
+    );
+
+    // The merge can't be finished until the binary file is chosen explicitly.
+    let root = Some(repository.path().to_path_buf());
+    match commands::snapshot(&None, &None, root.clone()) {
+        Err(Error::UnresolvedConflicts(files)) => {
+            assert_eq!(vec![PathBuf::from("data.bin")], files)
+        }
+        _ => panic!("binary conflicts should stop the merge from finishing"),
+    }
+    commands::track(
+        &[String::from("data.bin")],
+        &false,
+        &false,
+        &mut DebugOutput::new(),
+        root.clone(),
+    )
+    .unwrap();
+    commands::snapshot(&None, &None, root).expect("the merge should be finished");
+}
+
+#[test]
+fn test_tag_names() {
+    let repository = repository(&[("notes.txt", "one\n")]);
+    let root = Some(repository.path().to_path_buf());
+    let tag = |name: &str| {
+        commands::tag(
+            &mut DebugOutput::new(),
+            &Some(String::from(name)),
+            &None,
+            &None,
+            &false,
+            root.clone(),
+        )
+    };
+
+    let mut output = DebugOutput::new();
+    commands::log(&mut output, &None, root.clone()).unwrap();
+    let snapshot = output.print().unwrap_or_default()[0]
+        .trim_start_matches("snapshot ")
+        .to_string();
+
+    for name in ["", " ", "v1..v2", "HEAD", &snapshot] {
+        assert!(
+            matches!(tag(name), Err(Error::InvalidTagName(..))),
+            "'{}' should be rejected",
+            name
+        );
+    }
+    tag("v1.2").expect("a tag with a dot should be created");
+}
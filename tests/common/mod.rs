@@ -0,0 +1,55 @@
+// Each test binary uses a different part of these helpers.
+#![allow(dead_code)]
+
+use kifi::commands;
+use kifi::output::DebugOutput;
+use std::fs;
+use std::sync::Once;
+use tempfile::TempDir;
+
+static REGISTER: Once = Once::new();
+
+/// Registers the test user, with settings kept apart from the developer's own
+pub fn register() {
+    // Tests run in parallel and share environment variables, so the user is only registered once.
+    REGISTER.call_once(|| {
+        std::env::set_var(
+            "KIFI_CONFIG_DIR",
+            std::env::temp_dir().join("kifi-tests-config"),
+        );
+        commands::register(
+            &String::from("tester"),
+            &String::from("tester@example.com"),
+            &None,
+        )
+        .expect("the test user should be registered");
+    });
+}
+
+/// Creates a repository in a temporary directory, tracking and snapshotting the given files
+pub fn repository(files: &[(&str, &str)]) -> TempDir {
+    register();
+
+    let directory = TempDir::new().expect("a temporary directory should be created");
+    let root = Some(directory.path().to_path_buf());
+
+    for (name, contents) in files {
+        fs::write(directory.path().join(name), contents).expect("test files should be written");
+    }
+
+    commands::initialise(&mut DebugOutput::new(), root.clone())
+        .expect("the repository should be initialised");
+    let names: Vec<String> = files.iter().map(|(name, _)| name.to_string()).collect();
+    commands::track(
+        &names,
+        &false,
+        &false,
+        &mut DebugOutput::new(),
+        root.clone(),
+    )
+    .expect("test files should be tracked");
+    commands::snapshot(&Some(String::from("Initial snapshot")), &None, root)
+        .expect("the snapshot should be taken");
+
+    directory
+}
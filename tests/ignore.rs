@@ -0,0 +1,133 @@
+mod common;
+
+use common::repository;
+use kifi::commands;
+use kifi::errors::Error;
+use kifi::output::{DebugOutput, Output};
+use std::fs;
+use std::path::PathBuf;
+
+fn status(repository: &tempfile::TempDir) -> Vec<String> {
+    let mut output = DebugOutput::new();
+    commands::status(
+        &mut output,
+        &true,
+        &true,
+        Some(repository.path().to_path_buf()),
+    )
+    .expect("status should succeed");
+    output.print().unwrap_or_default()
+}
+
+#[test]
+fn test_ignored_directories_are_skipped() {
+    let repository = repository(&[(".kignore", "target/\n*.log\n")]);
+    fs::create_dir_all(repository.path().join("target/debug")).unwrap();
+    fs::write(repository.path().join("target/debug/build.o"), "").unwrap();
+    fs::write(repository.path().join("target/output.txt"), "").unwrap();
+    fs::write(repository.path().join("server.log"), "").unwrap();
+
+    // Neither kifi's own directory nor the ignored one are searched, so their files aren't listed.
+    // The ignored directory is listed in their place, but kifi's own isn't.
+    assert_eq!(vec!["! server.log", "! target/"], status(&repository));
+
+    let root = Some(repository.path().to_path_buf());
+    let track = |forced: bool| {
+        commands::track(
+            &[String::from("target/output.txt")],
+            &false,
+            &forced,
+            &mut DebugOutput::new(),
+            root.clone(),
+        )
+    };
+    assert!(track(false).is_err());
+    track(true).expect("ignored files should be tracked when forced");
+
+    // Once a file inside it is tracked, the directory is searched again.
+    assert_eq!(
+        vec!["A target/output.txt", "! server.log"],
+        status(&repository)
+    );
+}
+
+#[test]
+fn test_check_ignore() {
+    let repository = repository(&[(".kignore", "# build output\n*.log\nbuild/\n")]);
+    fs::create_dir_all(repository.path().join("server")).unwrap();
+    fs::write(repository.path().join("server/.kignore"), "!*.log\n").unwrap();
+
+    let check = |verbose: bool, non_matching: bool| {
+        let mut output = DebugOutput::new();
+        commands::check_ignore(
+            &mut output,
+            &[
+                String::from("debug.log"),
+                String::from("server/debug.log"),
+                String::from("build/output.o"),
+                String::from("notes.txt"),
+            ],
+            &verbose,
+            &non_matching,
+            Some(repository.path().to_path_buf()),
+        )
+        .expect("paths should be checked");
+        output.print().unwrap_or_default()
+    };
+
+    assert_eq!(vec!["debug.log", "build/output.o"], check(false, false));
+    assert_eq!(
+        vec![
+            ".kignore:2:*.log\tdebug.log",
+            "server/.kignore:1:!*.log\tserver/debug.log",
+            ".kignore:3:build/\tbuild/output.o",
+        ],
+        check(true, false)
+    );
+    assert_eq!(
+        Some(&String::from("::\tnotes.txt")),
+        check(true, true).last()
+    );
+}
+
+#[test]
+fn test_track_ignored_directory() {
+    let repository = repository(&[(".kignore", "target/\n")]);
+    fs::create_dir_all(repository.path().join("target/debug")).unwrap();
+    fs::write(repository.path().join("target/output.txt"), "").unwrap();
+    fs::write(repository.path().join("target/debug/build.o"), "").unwrap();
+
+    let root = Some(repository.path().to_path_buf());
+    let track = |forced: bool| {
+        commands::track(
+            &[String::from("target/debug/")],
+            &false,
+            &forced,
+            &mut DebugOutput::new(),
+            root.clone(),
+        )
+    };
+    assert!(track(false).is_err());
+    track(true).expect("ignored directories should be tracked when forced");
+
+    // Only the tracked file is listed, the rest of the ignored directory is still skipped.
+    assert_eq!(vec!["A target/debug/build.o"], status(&repository));
+}
+
+#[test]
+fn test_track_ignored_file_by_path() {
+    let repository = repository(&[(".kignore", "*.log\n")]);
+    fs::write(repository.path().join("server.log"), "").unwrap();
+
+    // Naming an ignored file, however its path is written, asks for -f instead of skipping it.
+    match commands::track(
+        &[String::from("./server.log")],
+        &false,
+        &false,
+        &mut DebugOutput::new(),
+        Some(repository.path().to_path_buf()),
+    ) {
+        Err(Error::TrackIgnoredFile(file)) => assert_eq!(PathBuf::from("server.log"), file),
+        _ => panic!("tracking an ignored file by name should need -f"),
+    }
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::repository;
+use kifi::commands;
+use kifi::output::{DebugOutput, Output};
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_format_patch_and_apply() {
+    let original = repository(&[("notes.txt", "one\ntwo\nthree\nfour\nfive\n")]);
+    let root = Some(original.path().to_path_buf());
+    commands::tag(
+        &mut DebugOutput::new(),
+        &Some(String::from("base")),
+        &None,
+        &None,
+        &false,
+        root.clone(),
+    )
+    .expect("the snapshot should be tagged");
+
+    fs::write(
+        original.path().join("notes.txt"),
+        "one\ntwo\nthree\nFOUR\nfive\n",
+    )
+    .unwrap();
+    fs::write(original.path().join("added.txt"), "added\n").unwrap();
+    commands::track(
+        &[String::from("added.txt")],
+        &false,
+        &false,
+        &mut DebugOutput::new(),
+        root.clone(),
+    )
+    .unwrap();
+    commands::snapshot(
+        &Some(String::from("Shout four\n\nAnd add a file.")),
+        &None,
+        root.clone(),
+    )
+    .unwrap();
+
+    let patches = TempDir::new().unwrap();
+    let mut output = DebugOutput::new();
+    commands::format_patch(
+        &mut output,
+        "base..",
+        &Some(patches.path().to_path_buf()),
+        root,
+    )
+    .expect("the patch should be written");
+
+    let patch_file = patches.path().join("0001-shout-four.patch");
+    assert_eq!(Some(vec![patch_file.display().to_string()]), output.print());
+    let patch = fs::read_to_string(&patch_file).unwrap();
+    assert!(patch.contains("From: tester <tester@example.com>\n"));
+    assert!(patch.contains("Subject: [PATCH 1/1] Shout four\n\nAnd add a file.\n\n---\n"));
+
+    // The lines around the change have moved, so the hunk is applied at an offset.
+    let copy = repository(&[("notes.txt", "zero\none\ntwo\nthree\nfour\nfive\n")]);
+    let mut output = DebugOutput::new();
+    commands::apply(&mut output, &[patch_file], Some(copy.path().to_path_buf()))
+        .expect("the patch should be applied");
+
+    assert_eq!(
+        vec![
+            "Applying: Shout four",
+            "patching file added.txt",
+            "patching file notes.txt",
+            "Hunk #1 succeeded at 2 (offset 1 line).",
+        ],
+        output.print().unwrap_or_default()
+    );
+    assert_eq!(
+        "zero\none\ntwo\nthree\nFOUR\nfive\n",
+        fs::read_to_string(copy.path().join("notes.txt")).unwrap()
+    );
+    assert_eq!(
+        "added\n",
+        fs::read_to_string(copy.path().join("added.txt")).unwrap()
+    );
+}
+
+#[test]
+fn test_apply_rejected() {
+    let repository = repository(&[("notes.txt", "alpha\nbeta\n")]);
+    let patch = repository.path().join("change.patch");
+    fs::write(
+        &patch,
+        "--- a/notes.txt\n+++ b/notes.txt\n@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n",
+    )
+    .unwrap();
+
+    let mut output = DebugOutput::new();
+    commands::apply(&mut output, &[patch], Some(repository.path().to_path_buf()))
+        .expect("rejected hunks should be reported, not returned as errors");
+
+    assert_eq!(
+        vec![
+            "patching file notes.txt",
+            "Hunk #1 FAILED at 1.",
+            "1 out of 1 hunks FAILED -- saving rejects to file notes.txt.rej",
+            "Some changes could not be applied, and were saved to:",
+            "\tnotes.txt.rej",
+        ],
+        output.print().unwrap_or_default()
+    );
+    assert_eq!(
+        "alpha\nbeta\n",
+        fs::read_to_string(repository.path().join("notes.txt")).unwrap()
+    );
+    assert!(fs::read_to_string(repository.path().join("notes.txt.rej"))
+        .unwrap()
+        .contains("+TWO"));
+}
+
+#[test]
+fn test_apply_updates_tracked_files() {
+    let repository = repository(&[(".kignore", "build/\n"), ("old.txt", "old\n")]);
+    let patch = repository.path().join("change.patch");
+    fs::write(
+        &patch,
+        "--- a/old.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-old\n\
+         --- /dev/null\n+++ b/build/new.txt\n@@ -0,0 +1 @@\n+new\n",
+    )
+    .unwrap();
+
+    let root = Some(repository.path().to_path_buf());
+    commands::apply(&mut DebugOutput::new(), &[patch], root.clone())
+        .expect("the patch should be applied");
+
+    let mut output = DebugOutput::new();
+    commands::status(&mut output, &true, &false, root).unwrap();
+    assert_eq!(
+        vec!["A build/new.txt", "D old.txt", "? change.patch"],
+        output.print().unwrap_or_default()
+    );
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use common::repository;
+use kifi::commands::{self, DiffOptions, Whitespace};
+use kifi::output::{DebugOutput, Output};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn preview(root: &Path, options: &DiffOptions) -> Vec<String> {
+    let mut output = DebugOutput::new();
+    commands::preview(&mut output, &None, options, Some(PathBuf::from(root)))
+        .expect("preview should succeed");
+    output.print().unwrap_or_default()
+}
+
+#[test]
+fn test_preview_unchanged() {
+    let repository = repository(&[("notes.txt", "one\ntwo\nthree\n")]);
+
+    assert!(preview(repository.path(), &DiffOptions::default()).is_empty());
+}
+
+#[test]
+fn test_preview_modified() {
+    let repository = repository(&[
+        ("first.txt", "one\ntwo\nthree\n"),
+        ("second.txt", "alpha\nbeta\n"),
+    ]);
+    fs::write(repository.path().join("second.txt"), "alpha\ngamma\n").unwrap();
+
+    assert_eq!(
+        vec![
+            "second.txt",
+            "\x1B[91m- 2\t|beta\x1B[0m\n\x1B[32m+ 2\t|gamma\x1B[0m",
+            "",
+        ],
+        preview(repository.path(), &DiffOptions::default())
+    );
+}
+
+#[test]
+fn test_preview_unified() {
+    let repository = repository(&[("notes.txt", "one\ntwo\nthree\nfour\n")]);
+    fs::write(
+        repository.path().join("notes.txt"),
+        "one\nthree\nfour\nfive\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        vec![
+            "--- a/notes.txt",
+            "+++ b/notes.txt",
+            "@@ -1,4 +1,4 @@",
+            " one",
+            "-two",
+            " three",
+            " four",
+            "+five",
+        ],
+        preview(
+            repository.path(),
+            &DiffOptions {
+                unified: Some(3),
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn test_preview_deleted() {
+    let repository = repository(&[("kept.txt", "kept\n"), ("deleted.txt", "one\ntwo\n")]);
+    fs::remove_file(repository.path().join("deleted.txt")).unwrap();
+
+    assert_eq!(
+        vec![
+            "--- a/deleted.txt",
+            "+++ /dev/null",
+            "@@ -1,2 +0,0 @@",
+            "-one",
+            "-two",
+        ],
+        preview(
+            repository.path(),
+            &DiffOptions {
+                unified: Some(3),
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn test_preview_binary() {
+    let repository = repository(&[("image.png", "\x00\x01\x02")]);
+    fs::write(repository.path().join("image.png"), "\x00\x01\x02\x03").unwrap();
+
+    assert_eq!(
+        vec![
+            "Binary files a/image.png and b/image.png differ (3 -> 4 bytes, ae4b3280 -> 054edec1)"
+        ],
+        preview(
+            repository.path(),
+            &DiffOptions {
+                unified: Some(3),
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn test_preview_line_endings() {
+    let repository = repository(&[("crlf.txt", "one\ntwo\n"), ("eof.txt", "one\ntwo\n")]);
+    fs::write(repository.path().join("crlf.txt"), "one\r\ntwo\r\n").unwrap();
+    fs::write(repository.path().join("eof.txt"), "one\ntwo").unwrap();
+
+    assert_eq!(
+        vec![
+            "--- a/crlf.txt",
+            "+++ b/crlf.txt",
+            "@@ -1,2 +1,2 @@",
+            "-one",
+            "-two",
+            "+one\r",
+            "+two\r",
+            "--- a/eof.txt",
+            "+++ b/eof.txt",
+            "@@ -1,2 +1,2 @@",
+            " one",
+            "-two",
+            "+two",
+            "\\ No newline at end of file",
+        ],
+        preview(
+            repository.path(),
+            &DiffOptions {
+                unified: Some(3),
+                ..Default::default()
+            }
+        )
+    );
+
+    assert_eq!(
+        vec![
+            "--- a/eof.txt",
+            "+++ b/eof.txt",
+            "@@ -1,2 +1,2 @@",
+            " one",
+            "-two",
+            "+two",
+            "\\ No newline at end of file",
+        ],
+        preview(
+            repository.path(),
+            &DiffOptions {
+                unified: Some(3),
+                whitespace: Whitespace::IgnoreCrAtEol,
+                ..Default::default()
+            }
+        )
+    );
+
+    assert!(preview(
+        repository.path(),
+        &DiffOptions {
+            whitespace: Whitespace::IgnoreAll,
+            ..Default::default()
+        }
+    )
+    .is_empty());
+}
+
+#[test]
+fn test_diff_between_snapshots() {
+    let repository = repository(&[("first.txt", "one\n"), ("second.txt", "alpha\n")]);
+    let root = Some(repository.path().to_path_buf());
+    let tag = |name: &str| {
+        commands::tag(
+            &mut DebugOutput::new(),
+            &Some(String::from(name)),
+            &None,
+            &None,
+            &false,
+            root.clone(),
+        )
+        .unwrap();
+    };
+
+    tag("v1");
+    fs::write(repository.path().join("first.txt"), "two\n").unwrap();
+    fs::write(repository.path().join("second.txt"), "beta\n").unwrap();
+    commands::snapshot(&Some(String::from("Change both")), &None, root.clone()).unwrap();
+    tag("v2");
+    // The working tree isn't part of a diff between two snapshots.
+    fs::write(repository.path().join("first.txt"), "three\n").unwrap();
+
+    let mut output = DebugOutput::new();
+    commands::diff(
+        &mut output,
+        &Some(String::from("v1")),
+        &Some(String::from("v2")),
+        &[String::from("first.txt")],
+        &DiffOptions {
+            unified: Some(3),
+            ..Default::default()
+        },
+        root,
+    )
+    .expect("diff should succeed");
+    assert_eq!(
+        vec![
+            "--- a/first.txt",
+            "+++ b/first.txt",
+            "@@ -1 +1 @@",
+            "-one",
+            "+two",
+        ],
+        output.print().unwrap_or_default()
+    );
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use common::repository;
+use kifi::commands;
+use kifi::errors::Error;
+use kifi::output::{DebugOutput, Output};
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// Creates a repository with `notes.txt` and a `docs` directory, and tags its last snapshot `v1`
+fn documented_repository() -> TempDir {
+    let repository = repository(&[("notes.txt", "one\n")]);
+    let root = Some(repository.path().to_path_buf());
+    fs::create_dir(repository.path().join("docs")).unwrap();
+    fs::write(repository.path().join("docs/intro.txt"), "intro\n").unwrap();
+    fs::write(repository.path().join("docs/guide.txt"), "guide\n").unwrap();
+    commands::track(
+        &[String::from("docs")],
+        &false,
+        &false,
+        &mut DebugOutput::new(),
+        root.clone(),
+    )
+    .unwrap();
+    commands::snapshot(&Some(String::from("Add docs")), &None, root.clone()).unwrap();
+    commands::tag(
+        &mut DebugOutput::new(),
+        &Some(String::from("v1")),
+        &None,
+        &None,
+        &false,
+        root,
+    )
+    .unwrap();
+
+    repository
+}
+
+fn restore(repository: &TempDir, file_names: &[&str], dry_run: bool) -> Vec<String> {
+    let mut output = DebugOutput::new();
+    let file_names: Vec<String> = file_names.iter().map(|name| name.to_string()).collect();
+    commands::restore(
+        &mut output,
+        "v1",
+        &file_names,
+        &dry_run,
+        Some(repository.path().to_path_buf()),
+    )
+    .expect("restore should succeed");
+    output.print().unwrap_or_default()
+}
+
+#[test]
+fn test_restore_dry_run() {
+    let repository = documented_repository();
+    fs::write(repository.path().join("docs/intro.txt"), "changed\n").unwrap();
+    fs::remove_file(repository.path().join("docs/guide.txt")).unwrap();
+
+    assert_eq!(
+        vec![
+            "Would restore docs/guide.txt (Creating)",
+            "Would restore docs/intro.txt (Overwriting)",
+            "Would restore notes.txt (Unchanged)",
+        ],
+        restore(&repository, &["docs", "notes.txt"], true)
+    );
+    // Nothing is written by a dry run.
+    assert_eq!(
+        "changed\n",
+        fs::read_to_string(repository.path().join("docs/intro.txt")).unwrap()
+    );
+    assert!(!repository.path().join("docs/guide.txt").exists());
+}
+
+#[test]
+fn test_restore_subtree() {
+    let repository = documented_repository();
+    fs::write(repository.path().join("docs/intro.txt"), "changed\n").unwrap();
+    fs::remove_file(repository.path().join("docs/guide.txt")).unwrap();
+    fs::write(repository.path().join("notes.txt"), "two\n").unwrap();
+
+    assert_eq!(
+        vec![
+            "Restored docs/guide.txt (Creating)",
+            "Restored docs/intro.txt (Overwriting)",
+        ],
+        restore(&repository, &["docs"], false)
+    );
+    assert_eq!(
+        "intro\n",
+        fs::read_to_string(repository.path().join("docs/intro.txt")).unwrap()
+    );
+    assert_eq!(
+        "guide\n",
+        fs::read_to_string(repository.path().join("docs/guide.txt")).unwrap()
+    );
+
+    // Files outside the restored directory keep their changes.
+    let mut output = DebugOutput::new();
+    commands::status(
+        &mut output,
+        &true,
+        &false,
+        Some(repository.path().to_path_buf()),
+    )
+    .unwrap();
+    assert_eq!(vec!["M notes.txt"], output.print().unwrap_or_default());
+}
+
+#[test]
+fn test_revert_with_changes() {
+    let repository = documented_repository();
+    let root = Some(repository.path().to_path_buf());
+    let revert = |force: bool| {
+        let mut output = DebugOutput::new();
+        commands::revert(&mut output, String::from("v1"), &force, root.clone())
+            .map(|_| output.print().unwrap_or_default())
+    };
+    fs::write(repository.path().join("notes.txt"), "two\n").unwrap();
+    commands::snapshot(&Some(String::from("Add two")), &None, root.clone()).unwrap();
+    fs::write(repository.path().join("notes.txt"), "three\n").unwrap();
+
+    let error = revert(false).expect_err("reverting should refuse to overwrite changed files");
+    assert!(
+        matches!(&error, Error::UncommittedChanges(files) if files == &[PathBuf::from("notes.txt")])
+    );
+    let mut output = DebugOutput::new();
+    error.handle(&mut output);
+    assert!(output
+        .print()
+        .unwrap_or_default()
+        .contains(&String::from("\tnotes.txt")));
+    assert_eq!(
+        "three\n",
+        fs::read_to_string(repository.path().join("notes.txt")).unwrap()
+    );
+
+    // A forced revert stashes the changes, and they can be restored from the stash.
+    let lines = revert(true).expect("forced reverts should stash the changes");
+    let stash = lines[0]
+        .strip_prefix("Stashed uncommitted changes as ")
+        .expect("the stash should be reported")
+        .to_string();
+    assert_eq!(
+        "one\n",
+        fs::read_to_string(repository.path().join("notes.txt")).unwrap()
+    );
+
+    commands::restore(
+        &mut DebugOutput::new(),
+        &stash,
+        &[String::from("notes.txt")],
+        &false,
+        root,
+    )
+    .unwrap();
+    assert_eq!(
+        "three\n",
+        fs::read_to_string(repository.path().join("notes.txt")).unwrap()
+    );
+}
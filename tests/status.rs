@@ -0,0 +1,80 @@
+mod common;
+
+use common::repository;
+use kifi::commands;
+use kifi::errors::Error;
+use kifi::output::{DebugOutput, Output};
+use std::fs;
+use std::path::PathBuf;
+
+#[test]
+fn test_move_directory() {
+    let repository = repository(&[("notes.txt", "notes\n")]);
+    let root = Some(repository.path().to_path_buf());
+    fs::create_dir_all(repository.path().join("docs/guide")).unwrap();
+    fs::write(repository.path().join("docs/intro.txt"), "intro\n").unwrap();
+    fs::write(repository.path().join("docs/guide/usage.txt"), "usage\n").unwrap();
+    commands::track(
+        &[String::from("docs")],
+        &false,
+        &false,
+        &mut DebugOutput::new(),
+        root.clone(),
+    )
+    .unwrap();
+    commands::snapshot(&Some(String::from("Add docs")), &None, root.clone()).unwrap();
+
+    let move_file = |from: &str, to: &str| {
+        commands::move_file(
+            &String::from(from),
+            &String::from(to),
+            &mut DebugOutput::new(),
+            root.clone(),
+        )
+    };
+    move_file("docs", "manual").expect("directories should be moved");
+    assert!(matches!(
+        move_file("missing", "elsewhere"),
+        Err(Error::FileNotFoundInCache(_))
+    ));
+
+    let mut output = DebugOutput::new();
+    commands::status(&mut output, &true, &false, root).unwrap();
+    assert_eq!(
+        vec![
+            "R docs/guide/usage.txt -> manual/guide/usage.txt",
+            "R docs/intro.txt -> manual/intro.txt",
+        ],
+        output.print().unwrap_or_default()
+    );
+}
+
+#[test]
+fn test_remove_changed_file() {
+    let repository = repository(&[("notes.txt", "notes\n")]);
+    let root = Some(repository.path().to_path_buf());
+    let remove = |force: bool| {
+        commands::remove(
+            &String::from("notes.txt"),
+            &force,
+            &mut DebugOutput::new(),
+            root.clone(),
+        )
+    };
+
+    fs::write(repository.path().join("notes.txt"), "changed\n").unwrap();
+    match remove(false) {
+        Err(Error::UncommittedChanges(files)) => {
+            assert_eq!(vec![PathBuf::from("notes.txt")], files)
+        }
+        _ => panic!("removing should refuse to delete changes"),
+    }
+    assert!(repository.path().join("notes.txt").is_file());
+
+    remove(true).expect("forced removals should delete the changes");
+    assert!(!repository.path().join("notes.txt").exists());
+
+    let mut output = DebugOutput::new();
+    commands::status(&mut output, &true, &false, root).unwrap();
+    assert_eq!(vec!["D notes.txt"], output.print().unwrap_or_default());
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use kifi::commands::{self, DiffOptions};
+use kifi::output::{DebugOutput, Output};
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+/// Snapshots as older versions of kifi saved them, without trees, messages or parents
+#[derive(Serialize)]
+struct LegacySnapshot {
+    name: String,
+    author: String,
+    author_email: String,
+    created: SystemTime,
+}
+
+#[derive(Serialize)]
+struct LegacySnapshots {
+    list: Vec<LegacySnapshot>,
+}
+
+#[derive(Serialize)]
+struct LegacyRepoFile {
+    status: String,
+}
+
+#[derive(Serialize)]
+struct LegacyFileCache {
+    files: HashMap<PathBuf, LegacyRepoFile>,
+}
+
+/// Creates a repository in the layout older versions of kifi used, with a directory of files per snapshot
+fn legacy_repository(snapshots: &[(&str, &str)]) -> TempDir {
+    common::register();
+    let directory = TempDir::new().expect("a temporary directory should be created");
+    let kifi = directory.path().join(".kifi");
+    fs::create_dir(&kifi).unwrap();
+
+    let mut list = Vec::new();
+    for (index, (name, contents)) in snapshots.iter().enumerate() {
+        fs::create_dir(kifi.join(name)).unwrap();
+        fs::write(kifi.join(name).join("notes.txt"), contents).unwrap();
+        list.insert(
+            0,
+            LegacySnapshot {
+                name: name.to_string(),
+                author: String::from("tester"),
+                author_email: String::from("tester@example.com"),
+                created: SystemTime::UNIX_EPOCH + Duration::from_secs(index as u64 + 1),
+            },
+        );
+    }
+    let (_, contents) = snapshots.last().expect("at least one snapshot is given");
+    fs::write(directory.path().join("notes.txt"), contents).unwrap();
+
+    let files = HashMap::from([(
+        PathBuf::from("notes.txt"),
+        LegacyRepoFile {
+            status: String::from("Tracked"),
+        },
+    )]);
+    serde_cbor::to_writer(
+        fs::File::create(kifi.join("SNAPSHOTS.kifi")).unwrap(),
+        &LegacySnapshots { list },
+    )
+    .unwrap();
+    serde_cbor::to_writer(
+        fs::File::create(kifi.join("FILECACHE.kifi")).unwrap(),
+        &LegacyFileCache { files },
+    )
+    .unwrap();
+
+    directory
+}
+
+#[test]
+fn test_upgrade_legacy_repository() {
+    let repository = legacy_repository(&[("tester_1", "one\n"), ("tester_2", "one\ntwo\n")]);
+    let root = Some(repository.path().to_path_buf());
+    fs::write(repository.path().join("notes.txt"), "one\ntwo\nthree\n").unwrap();
+
+    let mut output = DebugOutput::new();
+    commands::preview(
+        &mut output,
+        &None,
+        &DiffOptions {
+            unified: Some(0),
+            ..Default::default()
+        },
+        root.clone(),
+    )
+    .expect("the legacy repository should be upgraded");
+    // The files are kept in the object store, so the copies made by older versions are removed.
+    for name in ["tester_1", "tester_2"] {
+        assert!(!repository.path().join(".kifi").join(name).exists());
+    }
+    assert_eq!(
+        vec![
+            "--- a/notes.txt",
+            "+++ b/notes.txt",
+            "@@ -2,0 +3 @@",
+            "+three"
+        ],
+        output.print().unwrap_or_default()
+    );
+
+    // Older snapshots are kept in order, and the files can be restored from them.
+    let mut output = DebugOutput::new();
+    commands::log(&mut output, &None, root.clone()).expect("the history should be shown");
+    let history: Vec<String> = output
+        .print()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|line| line.starts_with("snapshot "))
+        .collect();
+    assert_eq!(vec!["snapshot tester_2", "snapshot tester_1"], history);
+
+    commands::restore(
+        &mut DebugOutput::new(),
+        &String::from("tester_1"),
+        &[String::from("notes.txt")],
+        &false,
+        root,
+    )
+    .expect("files should be restored from an upgraded snapshot");
+    assert_eq!(
+        "one\n",
+        fs::read_to_string(repository.path().join("notes.txt")).unwrap()
+    );
+}